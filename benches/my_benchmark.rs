@@ -2,11 +2,50 @@ use criterion::{criterion_group, criterion_main, Criterion};
 
 use chareth::board;
 
+// Move lists on the stack (ArrayVec) rather than in a Vec allocated
+// for each list, median of five runs with the range in brackets:
+//
+//   move gen   308ns (201-334) with a Vec, 148ns (125-237) on the stack
+//   search     no difference beyond the spread between runs
+//
+// Packing Move into 32 bits made no difference to either beyond the
+// spread (move gen 279ns (190-418) before, 308ns (201-334) after).
+// What it saves is memory: a Move is 4 bytes and a ScoredMove 8.
 pub fn benchmark_move_gen(c: &mut Criterion) {
     chareth::initialize();
     let board = board::Board::from_fen(board::START_FEN);
 
-    c.bench_function("move gen", |b| b.iter(|| board.generate_all_moves()));
+    c.bench_function("move gen", |b| b.iter(|| board.generate_all_moves().moves.len()));
+}
+
+pub fn benchmark_make_move(c: &mut Criterion) {
+    chareth::initialize();
+    let mut board = board::Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+    let move_list = board.generate_all_moves();
+
+    c.bench_function("make move", |b| b.iter(|| {
+        for smv in move_list.moves.iter() {
+            if board.make_move(&smv.mv) {
+                board.undo_move();
+            }
+        }
+    }));
+}
+
+// Restoring a snapshot of the position instead of undoing the move
+pub fn benchmark_snapshot_restore(c: &mut Criterion) {
+    chareth::initialize();
+    let mut board = board::Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+    let move_list = board.generate_all_moves();
+
+    c.bench_function("snapshot restore", |b| b.iter(|| {
+        for smv in move_list.moves.iter() {
+            let state = board.snapshot();
+            if board.make_move(&smv.mv) {
+                board.restore_snapshot(&state);
+            }
+        }
+    }));
 }
 
 pub fn benchmark_eval(c: &mut Criterion) {
@@ -20,11 +59,10 @@ pub fn benchmark_search(c: &mut Criterion) {
     chareth::initialize();
     
     let mut board = board::Board::from_fen(board::START_FEN);
-    let mut info = board::SearchInfo::new(4, board::GameMode::None);
-    info.set_show_thinking(false);
+    let mut info = board::SearchInfo::new(4);
 
     c.bench_function("search", |b| b.iter(|| board.search(&mut info)));
 }
 
-criterion_group!(benches, benchmark_move_gen, benchmark_eval, benchmark_search);
+criterion_group!(benches, benchmark_move_gen, benchmark_make_move, benchmark_snapshot_restore, benchmark_eval, benchmark_search);
 criterion_main!(benches);
@@ -0,0 +1,77 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+use chareth::bookbuilder::BookBuilder;
+use chareth::pgn::PgnReader;
+
+// Opening book builder.
+//
+// Usage: book [options] <output.bin> <PGN files...>
+//
+// Options:
+//   --max-ply N     Moves are recorded up to this ply (default 20)
+//   --min-games N   Minimum number of games for a move (default 3)
+//   --min-score S   Minimum score for a move, from 0 to 1 (default 0.4)
+//
+// PGN files are read as streams, so they can be of any size.
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {} [--max-ply N] [--min-games N] [--min-score S] <output.bin> <PGN files...>", program);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let mut max_ply = 20;
+    let mut min_games = 3;
+    let mut min_score = 0.4;
+    let mut files = Vec::new();
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            option @ ("--max-ply" | "--min-games" | "--min-score") => {
+                let value = args.get(i + 1).unwrap_or_else(|| usage(&args[0]));
+                match option {
+                    "--max-ply" => max_ply = value.parse().expect("invalid max ply"),
+                    "--min-games" => min_games = value.parse().expect("invalid min games"),
+                    _ => min_score = value.parse().expect("invalid min score"),
+                }
+                i += 2;
+            }
+            _ => {
+                files.push(args[i].clone());
+                i += 1;
+            }
+        }
+    }
+    if files.len() < 2 {
+        usage(&args[0]);
+    }
+    let output = files.remove(0);
+
+    chareth::initialize();
+
+    let mut builder = BookBuilder::new(max_ply);
+    let mut games_read: u64 = 0;
+    for path in &files {
+        let file = File::open(path).unwrap_or_else(|e| {
+            eprintln!("error opening {}: {}", path, e);
+            process::exit(1);
+        });
+        for game in PgnReader::new(BufReader::new(file)) {
+            builder.add_game(&game.expect("error reading PGN"));
+            games_read += 1;
+            if games_read % 10000 == 0 {
+                println!("{} games, {} positions", builder.games(), builder.positions());
+            }
+        }
+    }
+    println!("Read {} games ({} skipped), {} position/move pairs", builder.games(), builder.skipped(), builder.positions());
+
+    let book = builder.build(min_games, min_score);
+    book.save(&output).expect("error writing book");
+    println!("Book with {} entries written to {}", book.len(), output);
+}
@@ -7,10 +7,16 @@ use chareth::{board,xboard,simpleloop};
 
 fn main() {
 
+    let args: Vec<String> = env::args().collect();
+
+    // Hash keys from another seed, set before any board is created
+    if args.len() == 3 && args[1] == "--zobrist-seed" {
+        board::set_zobrist_seed(args[2].parse().expect("invalid seed"));
+    }
+
     chareth::initialize();
 
     // Run a benchmark search if indicated by the command arguments:
-    let args: Vec<String> = env::args().collect();
     if args.len() == 3 && args[1] == "b" {
         board::benchmark_search(args[2].parse().unwrap());
 
@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+
+use chareth::board::DEFAULT_EVAL_PARAMS;
+use chareth::tuner::{self,Tuner};
+
+// Offline evaluation tuner.
+//
+// Usage: tune <positions file> [epochs] [learning rate] [output file]
+//
+// Each line of the positions file holds a FEN string followed by the
+// game result ("1-0", "0-1", "1/2-1/2", or "[1.0]" etc.).  The tuned
+// parameters are written in the same form as the constants in
+// src/board/evaluate.rs.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <positions file> [epochs] [learning rate] [output file]", args[0]);
+        std::process::exit(1);
+    }
+
+    let epochs: u32 = args.get(2).map_or(1000, |s| s.parse().expect("invalid epochs"));
+    let rate: f64 = args.get(3).map_or(1.0, |s| s.parse().expect("invalid learning rate"));
+    let output = args.get(4).map_or("tuned_params.txt", |s| s.as_str());
+
+    chareth::initialize();
+
+    let (positions, skipped) = tuner::load_positions(&args[1]).expect("error reading positions file");
+    println!("Loaded {} positions, skipped {} bad lines", positions.len(), skipped);
+
+    let mut tuner = Tuner::new(positions, &DEFAULT_EVAL_PARAMS);
+    let k = tuner.fit_k();
+    println!("Fitted K: {:.4}, initial error {:.6}", k, tuner.mean_error(k));
+
+    let error = tuner.optimize(epochs, rate, true);
+    println!("Final error {:.6}", error);
+
+    fs::write(output, tuner.params().to_string()).expect("error writing parameters");
+    println!("Parameters written to {}", output);
+}
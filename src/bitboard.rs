@@ -5,8 +5,10 @@ use bitintr::{Tzcnt,Popcnt,Lzcnt};
 use crate::board::{self,RANKS_ITER,FILES_ITER,Square};
 use crate::pieces::WHITE;
 
+pub const BB_RANK_1: u64 = 0x00000000000000FF;
 pub const BB_RANK_4: u64 = 0x00000000FF000000;
 pub const BB_RANK_5: u64 = 0x000000FF00000000;
+pub const BB_RANK_8: u64 = 0xFF00000000000000;
 pub const BB_FILE_A: u64 = 0x0101010101010101;
 pub const BB_FILE_H: u64 = 0x8080808080808080;
 
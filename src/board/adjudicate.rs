@@ -0,0 +1,166 @@
+// Resigning and offering draws.
+//
+// After each of the engine's searches the score is checked against
+// configurable thresholds.  The engine resigns once the score has
+// been below the resign score for several moves in a row, and offers
+// a draw once the score has stayed close to zero for several moves,
+// but not before a minimum number of moves has been played.  Scores
+// are from the engine's point of view.
+
+pub const DEFAULT_RESIGN_SCORE: i32 = 800;
+pub const DEFAULT_RESIGN_MOVES: u32 = 3;
+pub const DEFAULT_DRAW_SCORE: i32 = 10;
+pub const DEFAULT_DRAW_MOVES: u32 = 8;
+pub const DEFAULT_DRAW_MIN_MOVES: u32 = 40;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Decision {
+    Play,
+    OfferDraw,
+    Resign,
+}
+
+pub struct Adjudicator {
+    // Resign when the score is at or below -resign_score for
+    // resign_moves consecutive moves.  Zero moves turns resigning off.
+    pub resign_score: i32,
+    pub resign_moves: u32,
+    // Offer (and accept) draws when the absolute score is at most
+    // draw_score for draw_moves consecutive moves, from move number
+    // draw_min_moves on.  Zero moves turns offering draws off.
+    pub draw_score: i32,
+    pub draw_moves: u32,
+    pub draw_min_moves: u32,
+
+    // State of the current game
+    resign_count: u32,
+    draw_count: u32,
+    // Only offer a draw once while the score stays level
+    draw_offered: bool,
+    last_score: Option<i32>,
+}
+
+impl Adjudicator {
+    pub fn new() -> Adjudicator {
+        Adjudicator {
+            resign_score: DEFAULT_RESIGN_SCORE,
+            resign_moves: DEFAULT_RESIGN_MOVES,
+            draw_score: DEFAULT_DRAW_SCORE,
+            draw_moves: DEFAULT_DRAW_MOVES,
+            draw_min_moves: DEFAULT_DRAW_MIN_MOVES,
+
+            resign_count: 0,
+            draw_count: 0,
+            draw_offered: false,
+            last_score: None,
+        }
+    }
+
+    // Start of a new game
+    pub fn reset(&mut self) {
+        self.resign_count = 0;
+        self.draw_count = 0;
+        self.draw_offered = false;
+        self.last_score = None;
+    }
+
+    // Record the score of the engine's search at the given move
+    // number, and decide what to do
+    pub fn update(&mut self, score: i32, move_number: u32) -> Decision {
+        self.last_score = Some(score);
+
+        if score <= -self.resign_score {
+            self.resign_count += 1;
+        } else {
+            self.resign_count = 0;
+        }
+
+        if score.abs() <= self.draw_score {
+            self.draw_count += 1;
+        } else {
+            self.draw_count = 0;
+            self.draw_offered = false;
+        }
+
+        if self.resign_moves > 0 && self.resign_count >= self.resign_moves {
+            return Decision::Resign;
+        }
+
+        if self.draw_moves > 0 && ! self.draw_offered && self.draw_count >= self.draw_moves
+            && move_number >= self.draw_min_moves {
+            self.draw_offered = true;
+            return Decision::OfferDraw;
+        }
+
+        Decision::Play
+    }
+
+    // Whether to accept the opponent's draw offer: the engine must
+    // not be better, based on its last search
+    pub fn accept_draw(&self, move_number: u32) -> bool {
+        match self.last_score {
+            Some(score) if score <= -self.resign_score => true,
+            Some(score) => score <= self.draw_score && move_number >= self.draw_min_moves,
+            None => false,
+        }
+    }
+}
+
+impl Default for Adjudicator {
+    fn default() -> Adjudicator {
+        Adjudicator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resign() {
+        let mut adj = Adjudicator::new();
+        assert_eq!(adj.update(-900, 30), Decision::Play);
+        assert_eq!(adj.update(-950, 31), Decision::Play);
+        // The streak is broken
+        assert_eq!(adj.update(-500, 32), Decision::Play);
+        assert_eq!(adj.update(-900, 33), Decision::Play);
+        assert_eq!(adj.update(-900, 34), Decision::Play);
+        assert_eq!(adj.update(-900, 35), Decision::Resign);
+
+        adj.reset();
+        adj.resign_moves = 0;
+        for n in 0..10 {
+            assert_eq!(adj.update(-28000, n), Decision::Play);
+        }
+    }
+
+    #[test]
+    fn offer_draw() {
+        let mut adj = Adjudicator::new();
+        adj.draw_moves = 2;
+        // Too early in the game
+        assert_eq!(adj.update(0, 10), Decision::Play);
+        assert_eq!(adj.update(0, 11), Decision::Play);
+
+        assert_eq!(adj.update(5, 40), Decision::OfferDraw);
+        // Offered only once
+        assert_eq!(adj.update(5, 41), Decision::Play);
+        assert_eq!(adj.update(50, 42), Decision::Play);
+        assert_eq!(adj.update(-5, 43), Decision::Play);
+        assert_eq!(adj.update(-5, 44), Decision::OfferDraw);
+    }
+
+    #[test]
+    fn accept_draw() {
+        let mut adj = Adjudicator::new();
+        assert!(! adj.accept_draw(50));
+        adj.update(0, 50);
+        assert!(adj.accept_draw(50));
+        assert!(! adj.accept_draw(20));
+        adj.update(150, 51);
+        assert!(! adj.accept_draw(51));
+        // Always accept when lost
+        adj.update(-1000, 10);
+        assert!(adj.accept_draw(10));
+    }
+}
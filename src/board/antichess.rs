@@ -0,0 +1,128 @@
+// Antichess evaluation.
+//
+// The aim is to give away all pieces, so the usual evaluation is
+// turned around: every piece counts against its side.  Pieces that
+// are hard to give away count the most.  A lone rook or queen can
+// run from the opponent's pieces for a long time, while pawns,
+// knights and kings are easily forced into captures.
+//
+// A piece that the opponent attacks is easy to give away, as the
+// opponent will have to take it.
+
+use crate::board::*;
+
+// Cost of each piece for its side, by piece type
+const PIECE_COST: [i32; 6] = [100, 150, 200, 300, 350, 150];
+
+// Bonus for each piece attacked by the opponent
+const ATTACKED_BONUS: i32 = 20;
+
+impl Board {
+    // Evaluate an antichess position, for side to move
+    pub(super) fn evaluate_antichess(&self) -> i32 {
+        // Score is counted for white
+        let mut score = 0;
+        for piece in PIECE_TYPES.iter() {
+            let cost = PIECE_COST[*piece as usize % 6] * self.bitboards[*piece as usize].count();
+            if piece.color() == WHITE { score -= cost } else { score += cost }
+        }
+
+        let occupancy = self.bb_sides[BOTH].0;
+        for sq in self.bb_sides[WHITE] {
+            if self.square_attacked_with(sq, BLACK, occupancy) {
+                score += ATTACKED_BONUS;
+            }
+        }
+        for sq in self.bb_sides[BLACK] {
+            if self.square_attacked_with(sq, WHITE, occupancy) {
+                score -= ATTACKED_BONUS;
+            }
+        }
+
+        if self.side == WHITE { score } else { -score }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn antichess(fen: &str) -> Board {
+        let mut board = Board::from_fen(fen);
+        board.set_variant(Variant::Antichess);
+        board
+    }
+
+    fn move_strings(board: &Board) -> Vec<String> {
+        let mut moves: Vec<String> = board.generate_all_moves().moves.iter().map(|smv| smv.mv.to_string()).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn forced_captures() {
+        // After 1. e3 b5, white must take the pawn
+        let mut board = antichess("rnbqkbnr/p1pppppp/8/1p6/8/4P3/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        assert_eq!(move_strings(&board), vec!["f1b5"]);
+        let mv = board.parse_move("f1b5").unwrap();
+        assert!(board.make_move(&mv));
+        assert!(board.generate_all_captures().moves.is_empty());
+        assert!(! board.generate_all_moves().moves.is_empty());
+
+        // No castling, and the king can move into check
+        let board = antichess("4k3/8/8/8/8/8/3r4/R3K3 w Q - 0 1");
+        assert!(board.parse_move("a1a8").is_none());
+        assert_eq!(move_strings(&board), vec!["e1d2"]);
+        let board = antichess("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        assert!(board.parse_move("e1c1").is_none());
+        assert!(board.parse_move("e1f2").is_some());
+        assert!(! board.in_check());
+    }
+
+    #[test]
+    fn king_promotion() {
+        let mut board = antichess("8/P7/8/8/8/8/8/7k w - - 0 1");
+        assert_eq!(move_strings(&board), vec!["a7a8b", "a7a8k", "a7a8n", "a7a8q", "a7a8r"]);
+        let mv = board.parse_move("a7a8k").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.to_fen(), "K7/8/8/8/8/8/8/7k b - - 0 1");
+        board.undo_move();
+        assert_eq!(board.to_fen(), "8/P7/8/8/8/8/8/7k w - - 0 1");
+    }
+
+    #[test]
+    fn winners() {
+        // White has given away all pieces
+        let mut board = antichess("8/8/8/8/8/8/1p6/R7 b - - 0 1");
+        let mv = board.parse_move("b2a1q").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.variant_winner(), Some(WHITE));
+        assert!(board.generate_all_moves().moves.is_empty());
+
+        // Stalemate wins
+        let mut board = antichess("8/8/8/8/8/p7/P7/8 w - - 0 1");
+        assert!(board.generate_all_moves().moves.is_empty());
+        assert_eq!(board.variant_winner(), None);
+        assert_eq!(board.game_status(), GameStatus::VariantWin(WHITE));
+
+        // The search finds the way to give away the last piece
+        let mut board = antichess("8/8/8/8/8/2p5/8/1R6 w - - 0 1");
+        let mut info = SearchInfo::new(4);
+        let result = board.search(&mut info);
+        assert_eq!(result.pv[0].to_string(), "b1b2");
+    }
+
+    #[test]
+    fn evaluation() {
+        // Fewer pieces is better
+        let board = antichess("8/8/8/8/8/2p5/8/1R2K3 w - - 0 1");
+        assert!(board.evaluate() < 0);
+        let board = antichess("8/8/3n4/8/8/2p5/8/4K3 w - - 0 1");
+        assert!(board.evaluate() > 0);
+
+        // A piece that has to be taken is nearly given away
+        let attacked = antichess("8/8/8/8/8/2p5/1R6/8 w - - 0 1");
+        let safe = antichess("8/8/8/8/8/2p5/R7/8 w - - 0 1");
+        assert!(attacked.evaluate() > safe.evaluate());
+    }
+}
@@ -0,0 +1,354 @@
+// Polyglot opening books.
+//
+// A Polyglot book is a file of 16 byte entries sorted by position
+// key: the key (8 bytes), move (2), weight (2) and learn value (4),
+// all big endian.  Keys are computed from the standard Random64
+// numbers, so books made by other programs can be used.
+//
+// Moves are encoded with the to square in bits 0-5, the from square
+// in bits 6-11 and the promotion piece (1 knight to 4 queen) in bits
+// 12-14.  Castling is encoded as the king capturing its own rook.
+
+use std::fs::File;
+use std::io::{self,Read,Write};
+use rand::Rng;
+
+use crate::board::*;
+use crate::moves::{Move,square_string};
+use super::random64::RANDOM64;
+
+const ENTRY_SIZE: usize = 16;
+
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn from_bytes(bytes: &[u8]) -> BookEntry {
+        let mut key = [0; 8];
+        key.copy_from_slice(&bytes[0..8]);
+        BookEntry {
+            key: u64::from_be_bytes(key),
+            mv: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+// How to choose between the book moves for a position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BookSelection {
+    // Randomly, in proportion to the weights
+    Weighted,
+    // Always the move with the highest weight
+    Best,
+}
+
+pub struct Book {
+    // Sorted by key
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        // A stable sort keeps the order of moves for each position
+        entries.sort_by_key(|entry| entry.key);
+        Book{entries: entries}
+    }
+
+    pub fn load(path: &str) -> io::Result<Book> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "book size is not a multiple of the entry size"));
+        }
+        Ok(Book::from_entries(bytes.chunks(ENTRY_SIZE).map(BookEntry::from_bytes).collect()))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for entry in &self.entries {
+            file.write_all(&entry.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // All entries for a position key
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // Legal book moves for the position, with their weights
+    pub fn moves(&self, board: &mut Board) -> Vec<(Move, u16)> {
+        self.entries(board.polyglot_key()).iter()
+            .filter_map(|entry| board.decode_book_move(entry.mv).map(|mv| (mv, entry.weight)))
+            .collect()
+    }
+
+    pub fn probe(&self, board: &mut Board, selection: BookSelection) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+        select(&moves, selection, rand::thread_rng().gen_range(0, total))
+    }
+}
+
+// Book settings for the protocol loops, set with the OwnBook,
+// BookFile and Best Book Move options
+pub struct BookOptions {
+    pub own_book: bool,
+    pub selection: BookSelection,
+    book: Option<Book>,
+}
+
+impl BookOptions {
+    pub fn new() -> BookOptions {
+        BookOptions{own_book: false, selection: BookSelection::Weighted, book: None}
+    }
+
+    // An empty path unloads the book
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        self.book = None;
+        if ! path.is_empty() {
+            self.book = Some(Book::load(path)?);
+        }
+        Ok(())
+    }
+
+    pub fn book(&self) -> Option<&Book> {
+        self.book.as_ref()
+    }
+
+    // A book move, if the engine uses its own book
+    pub fn probe(&self, board: &mut Board) -> Option<Move> {
+        match &self.book {
+            Some(book) if self.own_book => book.probe(board, self.selection),
+            _ => None,
+        }
+    }
+}
+
+impl Default for BookOptions {
+    fn default() -> BookOptions {
+        BookOptions::new()
+    }
+}
+
+// Pick a move.  For weighted selection, pick is a number below the
+// total weight.
+fn select(moves: &[(Move, u16)], selection: BookSelection, mut pick: u32) -> Option<Move> {
+    match selection {
+        BookSelection::Best => {
+            // The first of equal weights
+            let mut best: Option<(Move, u16)> = None;
+            for &(mv, weight) in moves {
+                if weight > 0 && best.is_none_or(|(_, w)| weight > w) {
+                    best = Some((mv, weight));
+                }
+            }
+            best.map(|(mv, _)| mv)
+        }
+        BookSelection::Weighted => {
+            for &(mv, weight) in moves {
+                if pick < weight as u32 {
+                    return Some(mv);
+                }
+                pick -= weight as u32;
+            }
+            None
+        }
+    }
+}
+
+impl Board {
+    // Position key as used by Polyglot books.  En passant is only
+    // included if a pawn can actually make the capture.
+    pub fn polyglot_key(&self) -> u64 {
+        let mut key = 0;
+
+        for sq in 0..64 {
+            let piece = self.pieces[sq];
+            if piece.exists() {
+                let p = piece as usize;
+                // Black pieces first for each piece type
+                let kind = 2 * (p % 6) + if piece.color() == WHITE { 1 } else { 0 };
+                key ^= RANDOM64[64 * kind + sq];
+            }
+        }
+
+        for (i, &perm) in [Castling::WK, Castling::WQ, Castling::BK, Castling::BQ].iter().enumerate() {
+            if self.castle_perm & perm != 0 {
+                key ^= RANDOM64[CASTLE_OFFSET + i];
+            }
+        }
+
+        if self.en_pas != Position::NONE as Square {
+            let (pawn, pawn_rank_sq) = if self.side == WHITE {
+                (Piece::WP, self.en_pas - 8)
+            } else {
+                (Piece::BP, self.en_pas + 8)
+            };
+            let file = self.en_pas % 8;
+            let capture = (file > 0 && self.pieces[pawn_rank_sq as usize - 1] == pawn)
+                || (file < 7 && self.pieces[pawn_rank_sq as usize + 1] == pawn);
+            if capture {
+                key ^= RANDOM64[EN_PASSANT_OFFSET + file as usize];
+            }
+        }
+
+        if self.side == WHITE {
+            key ^= RANDOM64[TURN_OFFSET];
+        }
+
+        key
+    }
+
+    // Returns the move if it is legal in this position
+    pub fn decode_book_move(&mut self, book_move: u16) -> Option<Move> {
+        let from = ((book_move >> 6) & 63) as Square;
+        // Castling is the king capturing its rook, which parse_move
+        // understands
+        let to = (book_move & 63) as Square;
+
+        let mut move_str = format!("{}{}", square_string(from), square_string(to));
+        match (book_move >> 12) & 7 {
+            1 => move_str.push('n'),
+            2 => move_str.push('b'),
+            3 => move_str.push('r'),
+            4 => move_str.push('q'),
+            _ => (),
+        }
+
+        let mv = self.parse_move(&move_str)?;
+        if ! self.make_move(&mv) {
+            return None;
+        }
+        self.undo_move();
+        Some(mv)
+    }
+
+    pub fn encode_book_move(&self, mv: &Move) -> u16 {
+        let from = mv.from() as u16;
+        let to = if mv.is_castle() {
+            // Rook square
+            self.castle_rook(mv.to()) as u16
+        } else {
+            mv.to() as u16
+        };
+        let promote = match mv.to_string().chars().nth(4) {
+            Some('n') => 1,
+            Some('b') => 2,
+            Some('r') => 3,
+            Some('q') => 4,
+            _ => 0,
+        };
+        (promote << 12) | (from << 6) | to
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Play moves from the start position
+    fn play(moves: &[&str]) -> Board {
+        let mut board = Board::from_fen(START_FEN);
+        for move_str in moves {
+            let mv = board.parse_move(move_str).unwrap();
+            assert!(board.make_move(&mv));
+            board.reset_ply();
+        }
+        board
+    }
+
+    #[test]
+    fn polyglot_keys() {
+        // Examples from the Polyglot book format specification
+        let positions: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"], 0x652a607ca3f242c1),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"], 0x00fdd303c946bdd9),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"], 0x3c8123ea7b067637),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"], 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in positions.iter() {
+            assert_eq!(play(moves).polyglot_key(), *key, "{:?}", moves);
+        }
+    }
+
+    #[test]
+    fn probe_book() {
+        let mut board = Board::from_fen(START_FEN);
+        let key = board.polyglot_key();
+        let e2e4 = board.parse_move("e2e4").unwrap();
+        let d2d4 = board.parse_move("d2d4").unwrap();
+        let castle_board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castle = castle_board.parse_move("e1g1").unwrap();
+
+        let entry = |key, mv, weight| BookEntry{key: key, mv: mv, weight: weight, learn: 0};
+        let book = Book::from_entries(vec![
+            entry(castle_board.polyglot_key(), castle_board.encode_book_move(&castle), 1),
+            entry(key, board.encode_book_move(&e2e4), 10),
+            entry(key, board.encode_book_move(&d2d4), 30),
+            // Illegal, and ignored
+            entry(key, 0x0fff, 100),
+        ]);
+
+        let path = std::env::temp_dir().join(format!("chareth-test-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        book.save(path).unwrap();
+        let book = Book::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(book.len(), 4);
+
+        // Castling is stored as e1h1
+        assert_eq!(castle_board.encode_book_move(&castle), 0x0107);
+
+        let moves = book.moves(&mut board);
+        assert_eq!(moves.len(), 2);
+        assert_eq!(book.probe(&mut board, BookSelection::Best).unwrap().to_string(), "d2d4");
+        assert_eq!(select(&moves, BookSelection::Weighted, 9).unwrap().to_string(), "e2e4");
+        assert_eq!(select(&moves, BookSelection::Weighted, 10).unwrap().to_string(), "d2d4");
+        assert!(book.probe(&mut board, BookSelection::Weighted).is_some());
+
+        let mut castle_board = castle_board;
+        assert_eq!(book.probe(&mut castle_board, BookSelection::Best).unwrap().to_string(), "e1g1");
+
+        let mut board = play(&["e2e4"]);
+        assert!(book.probe(&mut board, BookSelection::Best).is_none());
+    }
+}
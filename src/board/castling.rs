@@ -0,0 +1,215 @@
+// Castling, for standard chess and Chess960.
+//
+// Castling rights are the four permission bits in castle_perm, and
+// each right has the starting square of its rook in castle_rooks.
+// After castling the king and rook end up on the same squares as in
+// standard chess (g1 and f1, or c1 and d1), wherever they started.
+//
+// Castling moves are stored with the king's destination as the to
+// square, as in standard chess.  In Chess960 that can be the king's
+// own square, or the square of a normal king move, so castling is
+// written as the king capturing its own rook (UCI_Chess960), or as
+// O-O and O-O-O (xboard).
+
+use crate::board::*;
+use crate::moves::{Move,square_string};
+
+impl Board {
+    // Index of the castling right used by a castling move, from the
+    // king's destination
+    pub(super) fn castle_index(to: Square) -> usize {
+        let side = if to < 8 { 0 } else { 2 };
+        if to % 8 == 6 { side } else { side + 1 }
+    }
+
+    pub(super) fn castle_rook(&self, to: Square) -> Square {
+        self.castle_rooks[Board::castle_index(to)]
+    }
+
+    // Add a castling right from a FEN character.  The pieces must
+    // already be placed.  Rights without a matching king and rook are
+    // ignored.  Returns false for a character that isn't a castling
+    // right.
+    pub(super) fn add_castle_right(&mut self, c: char) -> bool {
+        if ! matches!(c.to_ascii_lowercase(), 'k' | 'q' | 'a'..='h') {
+            return false;
+        }
+        let (king, rook, rank) = if c.is_ascii_uppercase() {
+            (Piece::WK, Piece::WR, RANK_1)
+        } else {
+            (Piece::BK, Piece::BR, RANK_8)
+        };
+        let king_file = match (FILE_A..=FILE_H).find(|&file| self.pieces[fr_to_sq(file, rank) as usize] == king) {
+            Some(file) => file,
+            None => return true,
+        };
+        let is_rook = |file: FileRank| self.pieces[fr_to_sq(file, rank) as usize] == rook;
+
+        let rook_file = match c.to_ascii_lowercase() {
+            // Outermost rook
+            'k' => (king_file+1..=FILE_H).rev().find(|&file| is_rook(file)),
+            'q' => (FILE_A..king_file).find(|&file| is_rook(file)),
+            f => Some(f as FileRank - b'a').filter(|&file| is_rook(file) && file != king_file),
+        };
+        let rook_file = match rook_file {
+            Some(file) => file,
+            None => return true,
+        };
+
+        let index = if rank == RANK_1 { 0 } else { 2 } + if rook_file > king_file { 0 } else { 1 };
+        self.castle_perm |= 1 << index;
+        self.castle_rooks[index] = fr_to_sq(rook_file, rank);
+        true
+    }
+
+    // Set up castle_mask, so that a king or rook move (or a capture
+    // of the rook) removes the castling rights that it affects
+    pub(super) fn set_castle_mask(&mut self) {
+        self.castle_mask = [15; 64];
+        for i in 0..4 {
+            if self.castle_perm & (1 << i) == 0 {
+                continue;
+            }
+            let king = if i < 2 { Piece::WK } else { Piece::BK };
+            let rook_sq = self.castle_rooks[i];
+            self.castle_mask[rook_sq as usize] &= !(1 << i);
+            let rank = rook_sq / 8;
+            for file in FILES_ITER {
+                let sq = fr_to_sq(file, rank);
+                if self.pieces[sq as usize] == king {
+                    self.castle_mask[sq as usize] &= !(1 << i);
+                }
+            }
+        }
+    }
+
+    // X-FEN character for a castling right: KQkq for the outermost
+    // rook, otherwise the rook's file
+    pub(super) fn castle_char(&self, index: usize) -> char {
+        let rook_sq = self.castle_rooks[index];
+        let (rook, kingside) = if index < 2 { (Piece::WR, index == 0) } else { (Piece::BR, index == 2) };
+        let rank = rook_sq / 8;
+        let file = rook_sq % 8;
+        let outer_files = if kingside { file+1..FILE_H+1 } else { FILE_A..file };
+        let outermost = outer_files.into_iter().all(|f| self.pieces[fr_to_sq(f, rank) as usize] != rook);
+
+        let c = if outermost {
+            if kingside { 'k' } else { 'q' }
+        } else {
+            (b'a' + file) as char
+        };
+        if index < 2 { c.to_ascii_uppercase() } else { c }
+    }
+
+    // Format for writing moves from this game
+    pub fn move_format(&self, notation: CastleNotation) -> MoveFormat {
+        MoveFormat{notation: notation, castle_rooks: self.castle_rooks}
+    }
+}
+
+// How castling moves are written in coordinate notation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CastleNotation {
+    // The king's destination, e1g1 (standard chess)
+    KingMove,
+    // The king captures its own rook, e1h1 (UCI_Chess960)
+    KingTakesRook,
+    // O-O and O-O-O (xboard fischerandom)
+    OO,
+}
+
+#[derive(Clone, Copy)]
+pub struct MoveFormat {
+    notation: CastleNotation,
+    castle_rooks: [Square; 4],
+}
+
+impl MoveFormat {
+    pub fn standard() -> MoveFormat {
+        MoveFormat{notation: CastleNotation::KingMove, castle_rooks: STANDARD_CASTLE_ROOKS}
+    }
+
+    pub fn format(&self, mv: &Move) -> String {
+        if ! mv.is_castle() {
+            return mv.to_string();
+        }
+        let kingside = mv.to() % 8 == 6;
+        match self.notation {
+            CastleNotation::KingMove => mv.to_string(),
+            CastleNotation::KingTakesRook => {
+                let rook = self.castle_rooks[Board::castle_index(mv.to())];
+                format!("{}{}", square_string(mv.from()), square_string(rook))
+            }
+            CastleNotation::OO => String::from(if kingside { "O-O" } else { "O-O-O" }),
+        }
+    }
+
+    // A line of moves, such as a principal variation
+    pub fn line(&self, moves: &[Move]) -> String {
+        moves.iter().map(|mv| self.format(mv)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn castling_rights() {
+        // X-FEN and Shredder-FEN give the same rights
+        for fen in &["qbbnnrkr/8/8/8/8/8/8/QBBNNRKR w KFkf - 0 1", "qbbnnrkr/8/8/8/8/8/8/QBBNNRKR w HFhf - 0 1"] {
+            let board = Board::from_fen(fen);
+            assert_eq!(board.castle_perm, 15);
+            assert_eq!(board.castle_rooks, [7, 5, 63, 61]);
+            assert_eq!(board.to_fen(), "qbbnnrkr/8/8/8/8/8/8/QBBNNRKR w KQkq - 0 1");
+        }
+
+        // An inner rook needs its file in X-FEN
+        let fen = "rk2r3/8/8/8/8/8/8/RK2R2R w Eea - 0 1";
+        let board = Board::from_fen(fen);
+        assert_eq!(board.castle_rooks[0], 4);
+        assert_eq!(board.to_fen(), "rk2r3/8/8/8/8/8/8/RK2R2R w Ekq - 0 1");
+
+        assert_eq!(Board::from_fen(START_FEN).castle_rooks, STANDARD_CASTLE_ROOKS);
+    }
+
+    #[test]
+    fn castle_moves() {
+        // King on g1, so that kingside castling only moves the rook
+        let mut board = Board::from_fen("1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1");
+        let uci = board.move_format(CastleNotation::KingTakesRook);
+        let xboard = board.move_format(CastleNotation::OO);
+
+        let oo = board.parse_move("g1h1").unwrap();
+        assert!(oo.is_castle());
+        assert_eq!(oo.to_string(), "g1g1");
+        assert_eq!(uci.format(&oo), "g1h1");
+        assert_eq!(xboard.format(&oo), "O-O");
+        assert!(board.parse_move("O-O") == Some(oo));
+
+        // g1f1 is a normal king move
+        assert!(! board.parse_move("g1f1").unwrap().is_castle());
+
+        let ooo = board.parse_move("O-O-O").unwrap();
+        assert_eq!(uci.format(&ooo), "g1b1");
+        // The king's destination also works when it can't be mistaken
+        // for a normal king move
+        assert!(board.parse_move("g1c1") == Some(ooo));
+
+        let fen = board.to_fen();
+        let hash = board.get_position_hash();
+        assert!(board.make_move(&oo));
+        assert_eq!(board.to_fen(), "1r4kr/8/8/8/8/8/8/1R3RK1 b kq - 1 1");
+        board.undo_move();
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.get_position_hash(), hash);
+
+        assert!(board.make_move(&ooo));
+        assert_eq!(board.to_fen(), "1r4kr/8/8/8/8/8/8/2KR3R b kq - 1 1");
+
+        // The rook would block the check, but castling out of check
+        // isn't allowed
+        let board = Board::from_fen("6k1/8/8/8/8/8/8/r5KR w K - 0 1");
+        assert!(board.parse_move("O-O").is_none());
+    }
+}
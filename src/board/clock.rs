@@ -0,0 +1,61 @@
+// Clocks used to time searches.
+//
+// The search only sees elapsed time through the Clock trait, so tests
+// can substitute a clock that advances with the number of nodes
+// searched, which makes time-limited searches deterministic.
+
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    // Start timing
+    fn start(&mut self);
+
+    // Time since start, given the number of nodes searched so far
+    fn elapsed(&self, nodes: u64) -> Duration;
+}
+
+// Wall clock time
+pub struct SystemClock {
+    start_time: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock{start_time: Instant::now()}
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn start(&mut self) {
+        self.start_time = Instant::now();
+    }
+
+    fn elapsed(&self, _nodes: u64) -> Duration {
+        self.start_time.elapsed()
+    }
+}
+
+// Time advances by a fixed amount per node searched
+pub struct NodeClock {
+    per_node: Duration,
+}
+
+impl NodeClock {
+    pub fn new(per_node: Duration) -> NodeClock {
+        NodeClock{per_node: per_node}
+    }
+}
+
+impl Clock for NodeClock {
+    fn start(&mut self) {}
+
+    fn elapsed(&self, nodes: u64) -> Duration {
+        Duration::from_nanos(self.per_node.as_nanos() as u64 * nodes)
+    }
+}
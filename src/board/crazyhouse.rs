@@ -0,0 +1,177 @@
+// Crazyhouse pockets.
+//
+// A captured piece changes colour and goes into the capturing side's
+// pocket, from where it can later be dropped on any empty square
+// instead of making a move.  Pawns can't be dropped on the first or
+// last rank.  A promoted piece goes back into the pocket as a pawn,
+// so the squares of promoted pieces are kept in a bitboard.
+//
+// Pockets are indexed by piece, so pockets[WN] is the number of
+// knights that white can drop.  In FEN they follow the piece
+// placement, as in [Qn] or /Qn, and promoted pieces are marked with
+// a ~, as in Q~.
+
+use crate::board::*;
+
+// Largest number of one piece in a pocket: all the pawns, including
+// the ones that promoted
+pub(super) const POCKET_MAX: u8 = 16;
+
+impl Board {
+    // Number of a piece in its side's pocket
+    pub fn pocket(&self, piece: Piece) -> u8 {
+        self.pockets[piece as usize]
+    }
+
+    // The piece that a capture on sq puts into the capturing side's
+    // pocket
+    pub(super) fn pocket_piece(&self, sq: Square) -> Piece {
+        let captured = self.pieces[sq as usize];
+        if self.promoted.0 & (1 << sq) != 0 {
+            if captured.color() == WHITE { Piece::BP } else { Piece::WP }
+        } else {
+            captured.swap()
+        }
+    }
+
+    pub(super) fn add_to_pocket(&mut self, piece: Piece) {
+        debug_assert!(self.pockets[piece as usize] < POCKET_MAX);
+        self.hash_pocket(piece);
+        self.pockets[piece as usize] += 1;
+        self.hash_pocket(piece);
+    }
+
+    pub(super) fn remove_from_pocket(&mut self, piece: Piece) {
+        debug_assert!(self.pockets[piece as usize] > 0);
+        self.hash_pocket(piece);
+        self.pockets[piece as usize] -= 1;
+        self.hash_pocket(piece);
+    }
+
+    fn hash_pocket(&mut self, piece: Piece) {
+        self.hash ^= self.pocket_hash(piece);
+    }
+
+    pub(super) fn pocket_hash(&self, piece: Piece) -> u64 {
+        let count = self.pockets[piece as usize];
+        if count > 0 { self.hash_keys.pocket_keys[piece as usize][count as usize] } else { 0 }
+    }
+
+    // Add the pieces from the pocket field of a FEN, without the
+    // brackets.  Counts beyond POCKET_MAX are ignored.
+    pub(super) fn parse_pocket(&mut self, field: &str) {
+        for c in field.chars() {
+            match Piece::from_char(c) {
+                Some(piece) if ! piece.is_king() && self.pockets[piece as usize] < POCKET_MAX => {
+                    self.pockets[piece as usize] += 1;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Pocket for FEN, with brackets
+    pub(super) fn pocket_fen(&self) -> String {
+        let mut fen = String::from("[");
+        for piece in PIECE_TYPES.iter() {
+            for _ in 0..self.pockets[*piece as usize] {
+                fen.push_str(&piece.to_string());
+            }
+        }
+        fen.push(']');
+        fen
+    }
+
+    // Value of the pieces in the pockets, for white
+    pub(super) fn pocket_material(&self) -> i32 {
+        PIECE_TYPES.iter()
+            .map(|&piece| {
+                let value = self.pockets[piece as usize] as i32 * piece.value();
+                if piece.color() == WHITE { value } else { -value }
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crazyhouse(fen: &str) -> Board {
+        let mut board = Board::from_fen(fen);
+        board.set_variant(Variant::Crazyhouse);
+        board
+    }
+
+    #[test]
+    fn pocket_fen() {
+        let fen = "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[Pn] b KQkq - 0 1";
+        let board = crazyhouse(fen);
+        assert_eq!(board.pocket(Piece::WP), 1);
+        assert_eq!(board.pocket(Piece::BN), 1);
+        assert_eq!(board.to_fen(), fen);
+
+        // Pocket after a slash, and a promoted queen
+        let board = crazyhouse("4k3/1Q~6/8/8/4b3/8/Kpp5/8/NN b - - 0 1");
+        assert_eq!(board.pocket(Piece::WN), 2);
+        assert_eq!(board.to_fen(), "4k3/1Q~6/8/8/4b3/8/Kpp5/8[NN] b - - 0 1");
+
+        // Pockets and promoted pieces aren't written for other variants
+        let board = Board::from_fen("4k3/1Q~6/8/8/4b3/8/Kpp5/8/NN b - - 0 1");
+        assert_eq!(board.to_fen(), "4k3/1Q6/8/8/4b3/8/Kpp5/8 b - - 0 1");
+    }
+
+    #[test]
+    fn drops() {
+        let mut board = crazyhouse("4k3/8/8/8/8/8/8/4K3[Pn] w - - 0 1");
+        let hash = board.hash;
+
+        // Pawns can't be dropped on the first or last rank
+        assert!(board.parse_move("P@e8").is_none());
+        assert!(board.parse_move("P@a1").is_none());
+        assert!(board.parse_move("N@f3").is_none());
+        let mv = board.parse_move("P@e7").unwrap();
+        assert_eq!(mv.to_string(), "P@e7");
+        assert!(board.make_move(&mv));
+        assert_eq!(board.to_fen(), "4k3/4P3/8/8/8/8/8/4K3[n] b - - 0 1");
+        assert_eq!(board.hash, board.get_position_hash());
+
+        // Dropping a knight to give check
+        let mv = board.parse_move("n@f3").unwrap();
+        assert!(board.make_move(&mv));
+        assert!(board.in_check());
+        assert_eq!(board.to_fen(), "4k3/4P3/8/8/8/5n2/8/4K3[] w - - 1 2");
+
+        board.undo_move();
+        board.undo_move();
+        assert_eq!(board.hash, hash);
+        assert_eq!(board.pocket(Piece::WP), 1);
+    }
+
+    #[test]
+    fn promoted_captures() {
+        // The promoted queen goes back into the pocket as a pawn
+        let mut board = crazyhouse("4k3/1Q~6/8/8/4b3/8/Kpp5/8[] b - - 0 1");
+        let hash = board.hash;
+        let mv = board.parse_move("e4b7").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.pocket(Piece::BP), 1);
+        assert_eq!(board.pocket(Piece::BQ), 0);
+
+        // Promoting, and capturing the promoted piece
+        for move_str in &["a2b2", "c2c1q"] {
+            let mv = board.parse_move(move_str).unwrap();
+            assert!(board.make_move(&mv));
+        }
+        assert_eq!(board.to_fen(), "4k3/1b6/8/8/8/8/1K6/2q~5[Pp] w - - 0 3");
+        let mv = board.parse_move("b2c1").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.to_fen(), "4k3/1b6/8/8/8/8/8/2K5[PPp] b - - 0 3");
+
+        for _ in 0..4 {
+            board.undo_move();
+        }
+        assert_eq!(board.hash, hash);
+        assert_eq!(board.to_fen(), "4k3/1Q~6/8/8/4b3/8/Kpp5/8[] b - - 0 1");
+    }
+}
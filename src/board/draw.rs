@@ -0,0 +1,193 @@
+// Recognizing drawn positions from the material and pawn structure.
+//
+// A dead position is one where neither side can mate by any sequence
+// of legal moves, which ends the game as a draw under the rules.  The
+// search also treats as drawn some positions that can't be won against
+// correct defence, such as two knights against a bare king, or where
+// the pawns are locked and neither king can reach an enemy pawn.
+//
+// Only for standard chess, as the variants can be won in other ways.
+
+use crate::board::*;
+use super::endgame::DARK_SQUARES;
+
+impl Board {
+    // Neither side can give mate, so the game is drawn
+    pub(super) fn is_dead_position(&self) -> bool {
+        if self.variant != Variant::Standard {
+            return false;
+        }
+        let kings = self.bitboards[Piece::WK as usize].0 | self.bitboards[Piece::BK as usize].0;
+        let knights = self.bitboards[Piece::WN as usize].0 | self.bitboards[Piece::BN as usize].0;
+        let bishops = self.bitboards[Piece::WB as usize].0 | self.bitboards[Piece::BB as usize].0;
+        let others = self.bb_sides[BOTH].0 & ! kings;
+
+        // A lone knight, or any number of bishops that all move on the
+        // same colour.  With a knight on each side, or bishops on both
+        // colours, a mate can be helped along.
+        (others == knights && knights.count_ones() <= 1)
+            || (others == bishops && (bishops & DARK_SQUARES == 0 || bishops & ! DARK_SQUARES == 0))
+            || self.is_locked_pawns()
+    }
+
+    // Drawn with correct play, for the search: dead positions, along
+    // with two knights against a bare king and a single minor piece
+    // on each side
+    pub(super) fn is_draw_by_material(&self) -> bool {
+        if self.variant != Variant::Standard {
+            return false;
+        }
+        // Checked first, as this is called at every node
+        let majors = [Piece::WR, Piece::BR, Piece::WQ, Piece::BQ].iter().fold(0, |bb, &piece| bb | self.bitboards[piece as usize].0);
+        if majors != 0 {
+            return false;
+        }
+        let count = |piece: Piece| self.bitboards[piece as usize].count();
+        if count(Piece::WP) + count(Piece::BP) == 0 {
+            let minors = [count(Piece::WN) + count(Piece::WB), count(Piece::BN) + count(Piece::BB)];
+            if minors[WHITE] <= 1 && minors[BLACK] <= 1 {
+                return true;
+            }
+            for side in [WHITE, BLACK] {
+                let knights = if side == WHITE { count(Piece::WN) } else { count(Piece::BN) };
+                if minors[side] == 2 && knights == 2 && minors[side ^ 1] == 0 {
+                    return true;
+                }
+            }
+        }
+        self.is_dead_position()
+    }
+
+    // Only kings and pawns, with every pawn blocked by another pawn
+    // and neither king able to reach a square next to an enemy pawn.
+    // Nothing can then change apart from the kings moving around, so
+    // there can be no mate.
+    fn is_locked_pawns(&self) -> bool {
+        let pawns = [self.bitboards[Piece::WP as usize].0, self.bitboards[Piece::BP as usize].0];
+        let kings = self.bitboards[Piece::WK as usize].0 | self.bitboards[Piece::BK as usize].0;
+        if pawns[WHITE] == 0 || self.bb_sides[BOTH].0 != pawns[WHITE] | pawns[BLACK] | kings {
+            return false;
+        }
+        let all_pawns = pawns[WHITE] | pawns[BLACK];
+        if (pawns[WHITE] << 8) & ! all_pawns != 0 || (pawns[BLACK] >> 8) & ! all_pawns != 0 {
+            return false;
+        }
+
+        let attacks = |side: usize| {
+            let table: &[Bitboard; 64] = if side == WHITE { &WHITE_PAWN_MOVES } else { &BLACK_PAWN_MOVES };
+            Bitboard(pawns[side]).into_iter().fold(0, |bb, sq| bb | table[sq as usize].0)
+        };
+        let pawn_attacks = [attacks(WHITE), attacks(BLACK)];
+        if pawn_attacks[WHITE] & pawns[BLACK] != 0 {
+            return false;
+        }
+        // An en passant capture unlocks the pawns
+        if self.en_pas != Position::NONE as Square && pawn_attacks[self.side] & (1 << self.en_pas) != 0 {
+            return false;
+        }
+
+        let king_moves = |region: u64| Bitboard(region).into_iter().fold(0, |bb, sq| bb | KING_MOVES[sq as usize].0);
+        for side in [WHITE, BLACK] {
+            // Squares the king can walk to, avoiding the pawns and the
+            // squares that enemy pawns attack
+            let blocked = all_pawns | pawn_attacks[side ^ 1];
+            let mut region = 1 << self.king_sq[side];
+            loop {
+                let grown = region | (king_moves(region) & ! blocked);
+                if grown == region {
+                    break;
+                }
+                region = grown;
+            }
+            if king_moves(region) & pawns[side ^ 1] != 0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::*;
+
+    const DEAD: [&'static str; 8] = [
+        "8/8/4k3/8/8/4K3/8/8 w - - 0 1",
+        "8/8/4k3/8/8/3NK3/8/8 w - - 0 1",
+        "8/8/4k3/8/8/3BK3/8/8 b - - 0 1",
+        // Bishops on the same colour, on one side or both
+        "8/8/2b1k3/8/8/3BK3/8/8 w - - 0 1",
+        "8/8/4k3/8/8/2B1K3/1B6/B7 w - - 0 1",
+        // Locked pawns that the kings can't get past
+        "8/8/2k5/p1p1p1p1/P1P1P1P1/8/5K2/8 w - - 0 1",
+        "4k3/8/8/1p1p1p1p/pPpPpPpP/P1P1P1P1/8/4K3 b - - 0 1",
+        "8/1k6/8/p1p1p1p1/P1P1P1P1/8/4K3/8 w - - 0 1",
+    ];
+
+    // Drawn with correct play, but a mate is possible
+    const DRAWN: [&'static str; 4] = [
+        "8/8/4k3/8/8/2N1K3/3N4/8 w - - 0 1",
+        "8/8/4k1n1/8/8/3NK3/8/8 w - - 0 1",
+        "8/8/4kb2/8/8/3BK3/8/8 w - - 0 1",
+        "8/8/4k1n1/8/8/3BK3/8/8 b - - 0 1",
+    ];
+
+    const NOT_DRAWN: [&'static str; 9] = [
+        START_FEN,
+        "8/8/4k3/8/8/3RK3/8/8 w - - 0 1",
+        "8/8/4k3/8/4P3/4K3/8/8 w - - 0 1",
+        "8/8/4k3/8/8/2BNK3/8/8 w - - 0 1",
+        "8/8/4k3/8/8/2BBK3/8/8 w - - 0 1",
+        // Two knights against a pawn can win
+        "8/8/4k3/4p3/8/2N1K3/3N4/8 w - - 0 1",
+        // A gap in the pawns lets the king through
+        "8/8/2k5/p1p3p1/P1P3P1/8/5K2/8 w - - 0 1",
+        // A pawn can move or take
+        "8/8/2k5/p1p1pp2/P1P1P1P1/8/5K2/8 w - - 0 1",
+        // A pawn can take en passant
+        "4k3/8/8/1p1p1p1p/pPpPpPpP/P1P1P1P1/8/4K3 b - b3 0 1",
+    ];
+
+    #[test]
+    fn dead_positions() {
+        for fen in DEAD.iter() {
+            let board = Board::from_fen(fen);
+            assert!(board.is_dead_position(), "{}", fen);
+            assert!(board.is_draw_by_material(), "{}", fen);
+        }
+        for fen in DRAWN.iter() {
+            let board = Board::from_fen(fen);
+            assert!(! board.is_dead_position(), "{}", fen);
+            assert!(board.is_draw_by_material(), "{}", fen);
+        }
+        for fen in NOT_DRAWN.iter() {
+            let board = Board::from_fen(fen);
+            assert!(! board.is_dead_position(), "{}", fen);
+            assert!(! board.is_draw_by_material(), "{}", fen);
+        }
+
+        // Variants can be won without mating
+        let mut board = Board::from_fen(DEAD[0]);
+        board.set_variant(Variant::KingOfTheHill);
+        assert!(! board.is_dead_position());
+    }
+
+    #[test]
+    fn game_status() {
+        assert_eq!(Board::from_fen(DEAD[3]).game_status(), GameStatus::InsufficientMaterial);
+        assert_eq!(Board::from_fen(DRAWN[0]).game_status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn search_scores_draws() {
+        // Two knights can't force mate
+        let mut board = Board::from_fen(DRAWN[0]);
+        assert_eq!(board.search(&mut SearchInfo::new(4)).score, 0);
+
+        // Taking the rook leaves an extra pawn that can never move
+        let mut board = Board::from_fen("8/8/2k5/p1p1p1p1/P1P1P1P1/P7/6K1/7r w - - 0 1");
+        let result = board.search(&mut SearchInfo::new(4));
+        assert_eq!(result.best_move.map(|mv| mv.to_string()), Some(String::from("g2h1")));
+        assert_eq!(result.score, 0);
+    }
+}
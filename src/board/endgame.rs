@@ -0,0 +1,315 @@
+// Specialized endgame knowledge.
+//
+// Endgames are identified by a material key built from the piece
+// counts.  Endgames with a dedicated evaluator (KBNK, KQK, KRK, KPK)
+// replace the general evaluation entirely, while scale factors reduce
+// the general evaluation in endgames that are known to be drawish.
+
+use std::collections::HashMap;
+
+use crate::board::*;
+use crate::pieces::Piece;
+use super::kpk;
+
+// Added to evaluations of endgames that are known to be won, so that
+// the search prefers simplifying into them
+pub const KNOWN_WIN: i32 = 2000;
+
+// Scale factors are applied as score * factor / SCALE_NORMAL
+pub const SCALE_NORMAL: i32 = 64;
+const SCALE_DRAW: i32 = 0;
+
+pub(super) const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
+// Order of the piece counts in a material key.  Kings are implied.
+const KEY_PIECES: [Piece; 10] = [Piece::WP, Piece::WN, Piece::WB, Piece::WR, Piece::WQ,
+                                 Piece::BP, Piece::BN, Piece::BB, Piece::BR, Piece::BQ];
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum EndgameKind {
+    KBNK,
+    KQK,
+    KRK,
+    KPK,
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub struct Endgame {
+    pub kind: EndgameKind,
+    // The side with the extra material
+    pub strong: usize,
+}
+
+lazy_static! {
+    static ref ENDGAMES: HashMap<u64, Endgame> = get_endgames();
+}
+
+fn material_key(counts: &[i32; 10]) -> u64 {
+    // Six bits per count allows for any number of promoted pieces
+    counts.iter().enumerate().fold(0, |key, (i, &c)| key | (c as u64) << (6 * i))
+}
+
+// Material key of a signature such as "KBNK", where the pieces of the
+// strong side are listed first
+fn signature_key(signature: &str, strong: usize) -> u64 {
+    let mut counts = [0; 10];
+    let weak_start = signature[1..].find('K').unwrap() + 1;
+    for (i, c) in signature.chars().enumerate() {
+        let side = if i < weak_start { strong } else { strong ^ 1 };
+        let kind = match c {
+            'P' => 0,
+            'N' => 1,
+            'B' => 2,
+            'R' => 3,
+            'Q' => 4,
+            'K' => continue,
+            _ => panic!("invalid endgame signature"),
+        };
+        counts[side * 5 + kind] += 1;
+    }
+    material_key(&counts)
+}
+
+fn get_endgames() -> HashMap<u64, Endgame> {
+    let mut endgames = HashMap::new();
+    let signatures = [("KBNK", EndgameKind::KBNK), ("KQK", EndgameKind::KQK),
+                      ("KRK", EndgameKind::KRK), ("KPK", EndgameKind::KPK)];
+    for &(signature, kind) in signatures.iter() {
+        for &strong in &[WHITE, BLACK] {
+            endgames.insert(signature_key(signature, strong), Endgame{kind: kind, strong: strong});
+        }
+    }
+    endgames
+}
+
+// Not necessary as the lazy static is automatically initialized, but
+// provides a way to force initialization when the program starts
+pub fn init_endgames() {
+    lazy_static::initialize(&ENDGAMES);
+}
+
+fn file_of(sq: Square) -> i32 {
+    (sq % 8) as i32
+}
+
+fn rank_of(sq: Square) -> i32 {
+    (sq / 8) as i32
+}
+
+// Number of king moves between two squares
+pub fn distance(sq1: Square, sq2: Square) -> i32 {
+    std::cmp::max((file_of(sq1) - file_of(sq2)).abs(), (rank_of(sq1) - rank_of(sq2)).abs())
+}
+
+// Flip the board for the black side so that evaluators can be written
+// from white's point of view
+fn normalize(sq: Square, strong: usize) -> Square {
+    if strong == WHITE { sq } else { sq ^ 56 }
+}
+
+fn is_dark(sq: Square) -> bool {
+    DARK_SQUARES & (1 << sq) != 0
+}
+
+// Larger for squares closer to the edge of the board
+fn push_to_edge(sq: Square) -> i32 {
+    let centre_distance = |x: i32| if x < 4 { 3 - x } else { x - 4 };
+    20 * (centre_distance(file_of(sq)) + centre_distance(rank_of(sq)))
+}
+
+// Larger for kings that are closer together
+fn push_close(sq1: Square, sq2: Square) -> i32 {
+    140 - 20 * distance(sq1, sq2)
+}
+
+// Get the piece of the given side, from the white piece type
+fn side_piece(white_piece: Piece, side: usize) -> Piece {
+    if side == WHITE { white_piece } else { white_piece.swap() }
+}
+
+impl Board {
+    pub fn material_key(&self) -> u64 {
+        let mut counts = [0; 10];
+        for (i, piece) in KEY_PIECES.iter().enumerate() {
+            counts[i] = self.bitboards[*piece as usize].count();
+        }
+        material_key(&counts)
+    }
+
+    pub fn probe_endgame(&self) -> Option<Endgame> {
+        // All of the endgames with evaluators have at most four pieces
+        if self.bb_sides[BOTH].count() > 4 || self.variant != Variant::Standard {
+            return None;
+        }
+        ENDGAMES.get(&self.material_key()).cloned()
+    }
+
+    // Combine the general evaluation (for side to move) with any
+    // specialized endgame knowledge
+    pub(super) fn apply_endgame_knowledge(&self, score: i32) -> i32 {
+        // Endgames are different in the variants
+        if self.variant != Variant::Standard {
+            return score + self.evaluate_variant();
+        }
+        if let Some(endgame) = self.probe_endgame() {
+            return self.evaluate_endgame(endgame);
+        }
+        let white_score = if self.side == WHITE { score } else { -score };
+        let strong = if white_score >= 0 { WHITE } else { BLACK };
+        score * self.scale_factor(strong) / SCALE_NORMAL
+    }
+
+    // Evaluate a known endgame, for side to move
+    pub fn evaluate_endgame(&self, endgame: Endgame) -> i32 {
+        let strong = endgame.strong;
+        let strong_king = normalize(self.king_sq[strong], strong);
+        let weak_king = normalize(self.king_sq[strong^1], strong);
+
+        let score = match endgame.kind {
+            EndgameKind::KQK | EndgameKind::KRK => {
+                // Drive the weak king to the edge
+                let piece_val = if endgame.kind == EndgameKind::KQK { QUEEN_VAL } else { ROOK_VAL };
+                KNOWN_WIN + piece_val + push_to_edge(weak_king) + push_close(strong_king, weak_king)
+            }
+            EndgameKind::KBNK => {
+                // Mate is only possible in a corner of the bishop's color
+                let bishop = self.bitboards[side_piece(Piece::WB, strong) as usize].clone().pop_bit();
+                let corners = if is_dark(normalize(bishop, strong)) { [0, 63] } else { [7, 56] };
+                let manhattan = |sq: Square, corner: Square| (file_of(sq) - file_of(corner)).abs() + (rank_of(sq) - rank_of(corner)).abs();
+                let corner_distance = std::cmp::min(manhattan(weak_king, corners[0]), manhattan(weak_king, corners[1]));
+                KNOWN_WIN + BISHOP_VAL + KNIGHT_VAL + 20 * (14 - corner_distance) + push_close(strong_king, weak_king)
+            }
+            EndgameKind::KPK => {
+                let pawn = normalize(self.bitboards[side_piece(Piece::WP, strong) as usize].clone().pop_bit(), strong);
+                self.evaluate_kpk(strong_king, weak_king, pawn, self.side == strong)
+            }
+        };
+
+        if self.side == strong { score } else { -score }
+    }
+
+    // KPK evaluation with the pawn moving up the board, exact up to
+    // win or draw from the bitbase.  Returns the score for the side
+    // with the pawn.
+    fn evaluate_kpk(&self, strong_king: Square, weak_king: Square, pawn: Square, strong_to_move: bool) -> i32 {
+        // A pawn on the first or last rank, which only a FEN can give,
+        // never moves and isn't in the bitbase
+        let side = if strong_to_move { WHITE } else { BLACK };
+        if rank_of(pawn) < RANK_2 as i32 || rank_of(pawn) > RANK_7 as i32 || ! kpk::probe(side, strong_king, weak_king, pawn) {
+            return 0;
+        }
+        // Favor advancing the pawn, with the king close by
+        let queen_sq = fr_to_sq(file_of(pawn) as Square, RANK_8);
+        KNOWN_WIN + PAWN_VAL + 10 * rank_of(pawn) - 5 * distance(strong_king, queen_sq)
+    }
+
+    // Scale factor for the side with the better evaluation
+    pub fn scale_factor(&self, strong: usize) -> i32 {
+        let bb = |piece: Piece, side: usize| self.bitboards[side_piece(piece, side) as usize];
+        let weak = strong ^ 1;
+
+        let no_knights_rooks_queens = |side: usize| {
+            bb(Piece::WN, side).0 | bb(Piece::WR, side).0 | bb(Piece::WQ, side).0 == 0
+        };
+
+        // Opposite colored bishops, with only pawns otherwise
+        if bb(Piece::WB, WHITE).count() == 1 && bb(Piece::WB, BLACK).count() == 1
+            && no_knights_rooks_queens(WHITE) && no_knights_rooks_queens(BLACK)
+            && is_dark(bb(Piece::WB, WHITE).clone().pop_bit()) != is_dark(bb(Piece::WB, BLACK).clone().pop_bit()) {
+            let pawn_difference = (bb(Piece::WP, WHITE).count() - bb(Piece::WP, BLACK).count()).abs();
+            return if pawn_difference <= 1 { 16 } else { 32 };
+        }
+
+        // Rook pawns with a bishop (or none) that doesn't control the
+        // promotion square, and the defending king in the corner
+        let pawns = bb(Piece::WP, strong).0;
+        let weak_pieces = bb(Piece::WN, weak).0 | bb(Piece::WB, weak).0 | bb(Piece::WR, weak).0 | bb(Piece::WQ, weak).0;
+        if pawns != 0 && no_knights_rooks_queens(strong) && weak_pieces == 0 {
+            for &file in &[FILE_A, FILE_H] {
+                if pawns & !(bitboard::BB_FILE_A << file) != 0 {
+                    continue;
+                }
+                let queen_sq = if strong == WHITE { fr_to_sq(file, RANK_8) } else { fr_to_sq(file, RANK_1) };
+                let bishops = bb(Piece::WB, strong).0;
+                let covered = if is_dark(queen_sq) { bishops & DARK_SQUARES } else { bishops & !DARK_SQUARES };
+                if covered == 0 && distance(self.king_sq[weak], queen_sq) <= 1 {
+                    return SCALE_DRAW;
+                }
+            }
+        }
+
+        SCALE_NORMAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(fen: &str) -> Option<Endgame> {
+        Board::from_fen(fen).probe_endgame()
+    }
+
+    #[test]
+    fn material_signatures() {
+        assert_eq!(probe("8/8/8/4k3/8/8/8/1NB1K3 w - - 0 1"), Some(Endgame{kind: EndgameKind::KBNK, strong: WHITE}));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/1q2K3 w - - 0 1"), Some(Endgame{kind: EndgameKind::KQK, strong: BLACK}));
+        assert_eq!(probe("8/8/8/4k3/8/8/4P3/4K3 b - - 0 1"), Some(Endgame{kind: EndgameKind::KPK, strong: WHITE}));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/1NN1K3 w - - 0 1"), None);
+        assert_eq!(probe(START_FEN), None);
+    }
+
+    #[test]
+    fn kbnk_corner() {
+        // Dark squared bishop, so a1 and h8 are the mating corners
+        let right = Board::from_fen("8/8/8/8/8/2K5/8/k1B1N3 w - - 0 1").evaluate();
+        let wrong = Board::from_fen("8/8/8/8/8/5K2/8/4N1Bk w - - 0 1").evaluate();
+        assert!(right > KNOWN_WIN);
+        assert!(right > wrong);
+    }
+
+    #[test]
+    fn krk_edge() {
+        let edge = Board::from_fen("4k3/8/4K3/8/8/8/8/R7 b - - 0 1").evaluate();
+        let centre = Board::from_fen("8/8/8/4k3/8/8/2K5/R7 b - - 0 1").evaluate();
+        assert!(edge < -KNOWN_WIN);
+        assert!(edge < centre);
+    }
+
+    #[test]
+    fn kpk() {
+        // Defending king in the corner of a rook pawn
+        assert_eq!(Board::from_fen("k7/8/8/8/8/8/P7/7K w - - 0 1").evaluate(), 0);
+        // Pawn outside of the defending king's square
+        assert!(Board::from_fen("8/7k/8/8/1P6/8/8/K7 w - - 0 1").evaluate() > KNOWN_WIN);
+        assert!(Board::from_fen("8/8/8/8/1p6/8/7K/k7 b - - 0 1").evaluate() > KNOWN_WIN);
+        // Key square for the attacking king
+        assert!(Board::from_fen("4k3/8/3K4/8/4P3/8/8/8 b - - 0 1").evaluate() < -KNOWN_WIN);
+        // Stalemate with the defending king in front of the pawn
+        assert_eq!(Board::from_fen("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1").evaluate(), 0);
+        assert_eq!(Board::from_fen("8/8/8/8/8/3k4/3p4/3K4 w - - 0 1").evaluate(), 0);
+    }
+
+    #[test]
+    fn opposite_bishops() {
+        let board = Board::from_fen("4k3/5pp1/3b4/8/8/3B1PP1/5P2/4K3 w - - 0 1");
+        assert_eq!(board.scale_factor(WHITE), 16);
+        let same_color = Board::from_fen("4k3/5pp1/4b3/8/8/3B1PP1/5P2/4K3 w - - 0 1");
+        assert_eq!(same_color.scale_factor(WHITE), SCALE_NORMAL);
+    }
+
+    #[test]
+    fn wrong_rook_pawn() {
+        // Dark squared bishop can't cover a8
+        assert_eq!(Board::from_fen("k7/8/8/8/8/8/P7/K1B5 w - - 0 1").evaluate(), 0);
+        let right_bishop = Board::from_fen("k7/8/8/8/8/8/P7/KB6 w - - 0 1");
+        assert_eq!(right_bishop.scale_factor(WHITE), SCALE_NORMAL);
+        assert!(right_bishop.evaluate() > 0);
+    }
+}
@@ -1,5 +1,8 @@
+use std::fmt;
+
 use crate::board::*;
 use crate::pieces::Piece;
+use super::endgame::SCALE_NORMAL;
 
 const PAWN_ISOLATED_SCORE: i32 = -10;
 // Passed pawn bonus indexed by rank
@@ -7,73 +10,351 @@ const PAWN_PASSED_SCORE: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 200];
 
 const ENDGAME_MATERIAL: i32 = ROOK_VAL + 2*KNIGHT_VAL + 2*PAWN_VAL + KING_VAL;
 
+// White pieces in the order used by EvalParams::piece_values
+const MATERIAL_PIECES: [Piece; 5] = [Piece::WP, Piece::WN, Piece::WB, Piece::WR, Piece::WQ];
+
+// The individual weight groups that make up the evaluation.  Each
+// term indexes into its own slice of EvalParams (e.g. by square for
+// the piece square tables).
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum EvalTerm {
+    Material,
+    PawnIsolated,
+    PawnPassed,
+    PawnTable,
+    KnightTable,
+    BishopTable,
+    RookTable,
+    KingEndTable,
+    KingOpenTable,
+}
+
+impl EvalTerm {
+    pub const ALL: [EvalTerm; 9] = [
+        EvalTerm::Material, EvalTerm::PawnIsolated, EvalTerm::PawnPassed,
+        EvalTerm::PawnTable, EvalTerm::KnightTable, EvalTerm::BishopTable,
+        EvalTerm::RookTable, EvalTerm::KingEndTable, EvalTerm::KingOpenTable,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EvalTerm::Material => "Material",
+            EvalTerm::PawnIsolated => "Isolated pawns",
+            EvalTerm::PawnPassed => "Passed pawns",
+            EvalTerm::PawnTable => "Pawn squares",
+            EvalTerm::KnightTable => "Knight squares",
+            EvalTerm::BishopTable => "Bishop squares",
+            EvalTerm::RookTable => "Rook squares",
+            EvalTerm::KingEndTable => "King squares (end)",
+            EvalTerm::KingOpenTable => "King squares (open)",
+        }
+    }
+
+    // Name of the constant in the source that holds the default weights
+    pub fn const_name(&self) -> &'static str {
+        match *self {
+            EvalTerm::Material => "PIECE_VALUES",
+            EvalTerm::PawnIsolated => "PAWN_ISOLATED_SCORE",
+            EvalTerm::PawnPassed => "PAWN_PASSED_SCORE",
+            EvalTerm::PawnTable => "PAWN_TABLE",
+            EvalTerm::KnightTable => "KNIGHT_TABLE",
+            EvalTerm::BishopTable => "BISHOP_TABLE",
+            EvalTerm::RookTable => "ROOK_TABLE",
+            EvalTerm::KingEndTable => "KING_END_TABLE",
+            EvalTerm::KingOpenTable => "KING_OPEN_TABLE",
+        }
+    }
+}
+
+// All of the weights used by the evaluation function.  The engine
+// uses DEFAULT_EVAL_PARAMS; other values are used when tuning.
+#[derive(Clone)]
+pub struct EvalParams {
+    // Pawn, knight, bishop, rook, queen
+    pub piece_values: [i32; 5],
+    pub pawn_isolated: [i32; 1],
+    pub pawn_passed: [i32; 8],
+    pub pawn_table: [i32; 64],
+    pub knight_table: [i32; 64],
+    pub bishop_table: [i32; 64],
+    pub rook_table: [i32; 64],
+    pub king_end_table: [i32; 64],
+    pub king_open_table: [i32; 64],
+}
+
+pub const DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    piece_values: [PAWN_VAL, KNIGHT_VAL, BISHOP_VAL, ROOK_VAL, QUEEN_VAL],
+    pawn_isolated: [PAWN_ISOLATED_SCORE],
+    pawn_passed: PAWN_PASSED_SCORE,
+    pawn_table: PAWN_TABLE,
+    knight_table: KNIGHT_TABLE,
+    bishop_table: BISHOP_TABLE,
+    rook_table: ROOK_TABLE,
+    king_end_table: KING_END_TABLE,
+    king_open_table: KING_OPEN_TABLE,
+};
+
+impl EvalParams {
+    pub fn weights(&self, term: EvalTerm) -> &[i32] {
+        match term {
+            EvalTerm::Material => &self.piece_values,
+            EvalTerm::PawnIsolated => &self.pawn_isolated,
+            EvalTerm::PawnPassed => &self.pawn_passed,
+            EvalTerm::PawnTable => &self.pawn_table,
+            EvalTerm::KnightTable => &self.knight_table,
+            EvalTerm::BishopTable => &self.bishop_table,
+            EvalTerm::RookTable => &self.rook_table,
+            EvalTerm::KingEndTable => &self.king_end_table,
+            EvalTerm::KingOpenTable => &self.king_open_table,
+        }
+    }
+
+    pub fn weights_mut(&mut self, term: EvalTerm) -> &mut [i32] {
+        match term {
+            EvalTerm::Material => &mut self.piece_values,
+            EvalTerm::PawnIsolated => &mut self.pawn_isolated,
+            EvalTerm::PawnPassed => &mut self.pawn_passed,
+            EvalTerm::PawnTable => &mut self.pawn_table,
+            EvalTerm::KnightTable => &mut self.knight_table,
+            EvalTerm::BishopTable => &mut self.bishop_table,
+            EvalTerm::RookTable => &mut self.rook_table,
+            EvalTerm::KingEndTable => &mut self.king_end_table,
+            EvalTerm::KingOpenTable => &mut self.king_open_table,
+        }
+    }
+
+    // Offset of the term's first weight in the flattened parameter
+    // vector
+    pub fn offset(term: EvalTerm) -> usize {
+        let mut offset = 0;
+        for t in EvalTerm::ALL.iter().take_while(|&&t| t != term) {
+            offset += DEFAULT_EVAL_PARAMS.weights(*t).len();
+        }
+        offset
+    }
+
+    pub fn to_vec(&self) -> Vec<i32> {
+        EvalTerm::ALL.iter().flat_map(|&t| self.weights(t).iter().cloned()).collect()
+    }
+
+    pub fn from_slice(values: &[i32]) -> EvalParams {
+        let mut params = DEFAULT_EVAL_PARAMS;
+        let mut offset = 0;
+        for &term in EvalTerm::ALL.iter() {
+            let weights = params.weights_mut(term);
+            let n = weights.len();
+            weights.copy_from_slice(&values[offset..offset+n]);
+            offset += n;
+        }
+        assert_eq!(offset, values.len());
+        params
+    }
+}
+
+// Writes the parameters in the same form as the constants in this
+// file, so that tuned values can be pasted back in
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &term in EvalTerm::ALL.iter() {
+            let weights = self.weights(term);
+            if term == EvalTerm::PawnIsolated {
+                writeln!(f, "const {}: i32 = {};", term.const_name(), weights[0])?;
+                continue;
+            }
+            write!(f, "const {}: [i32; {}] = [", term.const_name(), weights.len())?;
+            if weights.len() == 64 {
+                for rank in weights.chunks(8) {
+                    write!(f, "\n   ")?;
+                    for w in rank {
+                        write!(f, " {},", w)?;
+                    }
+                }
+                writeln!(f, "\n];")?;
+            } else {
+                let values: Vec<String> = weights.iter().map(|w| w.to_string()).collect();
+                writeln!(f, "{}];", values.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Receives each weighted term as it is accumulated by the evaluation.
+// The contribution of a call is count * weight, added for white and
+// subtracted for black.
+pub trait EvalTrace {
+    fn add(&mut self, term: EvalTerm, index: usize, side: usize, count: i32, weight: i32);
+}
+
+pub struct NoTrace;
+
+impl EvalTrace for NoTrace {
+    #[inline(always)]
+    fn add(&mut self, _term: EvalTerm, _index: usize, _side: usize, _count: i32, _weight: i32) {}
+}
+
+// Per-side totals of each term, for explaining an evaluation.  Built
+// by tracing the evaluation itself so that it can't disagree with it.
+pub struct EvalBreakdown {
+    totals: [[i32; 2]; EvalTerm::ALL.len()],
+    // Whether each side's king was scored with the endgame table
+    endgame: [bool; 2],
+    side: usize,
+    // Score for the side to move from the general evaluation, before
+    // applying endgame knowledge
+    pub general: i32,
+    pub known_endgame: Option<Endgame>,
+    pub scale_factor: i32,
+    // Final score for the side to move
+    pub score: i32,
+}
+
+impl EvalTrace for EvalBreakdown {
+    fn add(&mut self, term: EvalTerm, _index: usize, side: usize, count: i32, weight: i32) {
+        self.totals[term as usize][side] += count * weight;
+        if term == EvalTerm::KingEndTable {
+            self.endgame[side] = true;
+        }
+    }
+}
+
+impl fmt::Display for EvalBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = |endgame| if endgame { "endgame" } else { "opening" };
+        writeln!(f, "{:20} {:>7} {:>7} {:>7}", "Term", "White", "Black", "Total")?;
+        for &term in EvalTerm::ALL.iter() {
+            let [white, black] = self.totals[term as usize];
+            writeln!(f, "{:20} {:>7} {:>7} {:>7}", term.name(), white, black, white - black)?;
+        }
+        writeln!(f, "Phase: white king {}, black king {}", phase(self.endgame[WHITE]), phase(self.endgame[BLACK]))?;
+        writeln!(f, "General score (side to move): {}", self.general)?;
+        match self.known_endgame {
+            Some(endgame) => writeln!(f, "Endgame: {:?} (strong side {})", endgame.kind, if endgame.strong == WHITE { "white" } else { "black" })?,
+            None => writeln!(f, "Scale factor: {}/{}", self.scale_factor, SCALE_NORMAL)?,
+        }
+        let white_score = if self.side == WHITE { self.score } else { -self.score };
+        writeln!(f, "Score (white): {}", white_score)?;
+        write!(f, "Score (side to move): {}", self.score)
+    }
+}
+
+#[inline(always)]
+fn weigh<T: EvalTrace>(params: &EvalParams, trace: &mut T, term: EvalTerm, index: usize, side: usize, count: i32) -> i32 {
+    let weight = params.weights(term)[index];
+    trace.add(term, index, side, count, weight);
+    count * weight
+}
+
 impl Board {
-    // Evaluate position for side to move
+    // Evaluate position for side to move, using the neural network if
+    // one has been loaded.  Antichess has its own evaluation.
     pub fn evaluate(&self) -> i32 {
+        if self.variant == Variant::Antichess {
+            return self.evaluate_antichess();
+        }
+        let score = match &self.nnue {
+            Some(nnue) => nnue.evaluate(self.side),
+            None => self.evaluate_with(&DEFAULT_EVAL_PARAMS, &mut NoTrace),
+        };
+        self.apply_endgame_knowledge(score)
+    }
+
+    // Breakdown of the handcrafted evaluation (the neural network
+    // evaluation is not traced)
+    pub fn evaluate_breakdown(&self) -> EvalBreakdown {
+        let mut breakdown = EvalBreakdown {
+            totals: [[0; 2]; EvalTerm::ALL.len()],
+            endgame: [false; 2],
+            side: self.side,
+            general: 0,
+            known_endgame: self.probe_endgame(),
+            scale_factor: SCALE_NORMAL,
+            score: 0,
+        };
+        breakdown.general = self.evaluate_with(&DEFAULT_EVAL_PARAMS, &mut breakdown);
+        let white_score = if self.side == WHITE { breakdown.general } else { -breakdown.general };
+        breakdown.scale_factor = self.scale_factor(if white_score >= 0 { WHITE } else { BLACK });
+        breakdown.score = self.apply_endgame_knowledge(breakdown.general);
+        breakdown
+    }
+
+    // Evaluate position for side to move using the given weights,
+    // reporting each term to trace
+    pub fn evaluate_with<T: EvalTrace>(&self, params: &EvalParams, trace: &mut T) -> i32 {
         // Score is counted for white, and then return negative if
         // black is to move
-        let mut score = self.material[WHITE] - self.material[BLACK];
+        let mut score = 0;
+
+        for (i, piece) in MATERIAL_PIECES.iter().enumerate() {
+            score += weigh(params, trace, EvalTerm::Material, i, WHITE, self.bitboards[*piece as usize].count());
+            score -= weigh(params, trace, EvalTerm::Material, i, BLACK, self.bitboards[piece.swap() as usize].count());
+        }
 
         for sq64 in self.bitboards[Piece::WP as usize].into_iter() {
-            score += PAWN_TABLE[sq64 as usize];
+            score += weigh(params, trace, EvalTerm::PawnTable, sq64 as usize, WHITE, 1);
 
             if self.bitboards[Piece::WP as usize].isolated_pawn(sq64) {
-                score += PAWN_ISOLATED_SCORE;
+                score += weigh(params, trace, EvalTerm::PawnIsolated, 0, WHITE, 1);
             }
 
             if self.bitboards[Piece::BP as usize].passed_pawn(sq64, WHITE) {
                 // Todo: formalize RANKS for sq64
-                score += PAWN_PASSED_SCORE[(sq64/8) as usize];
+                score += weigh(params, trace, EvalTerm::PawnPassed, (sq64/8) as usize, WHITE, 1);
             }
         }
 
         for sq64 in self.bitboards[Piece::BP as usize].into_iter() {
-            score -= PAWN_TABLE[MIRROR64[sq64 as usize]];
+            score -= weigh(params, trace, EvalTerm::PawnTable, MIRROR64[sq64 as usize], BLACK, 1);
 
             if self.bitboards[Piece::BP as usize].isolated_pawn(sq64) {
-                score -= PAWN_ISOLATED_SCORE;
+                score -= weigh(params, trace, EvalTerm::PawnIsolated, 0, BLACK, 1);
             }
 
             if self.bitboards[Piece::WP as usize].passed_pawn(sq64, BLACK) {
                 // Todo: formalize RANKS for sq64
-                score -= PAWN_PASSED_SCORE[(7 - sq64/8) as usize];
+                score -= weigh(params, trace, EvalTerm::PawnPassed, (7 - sq64/8) as usize, BLACK, 1);
             }
         }
 
         for sq64 in self.bitboards[Piece::WN as usize].into_iter() {
-            score += KNIGHT_TABLE[sq64 as usize];
+            score += weigh(params, trace, EvalTerm::KnightTable, sq64 as usize, WHITE, 1);
         }
 
         for sq64 in self.bitboards[Piece::BN as usize].into_iter() {
-            score -= KNIGHT_TABLE[MIRROR64[sq64 as usize]];
+            score -= weigh(params, trace, EvalTerm::KnightTable, MIRROR64[sq64 as usize], BLACK, 1);
         }
 
         for sq64 in self.bitboards[Piece::WB as usize].into_iter() {
-            score += BISHOP_TABLE[sq64 as usize];
+            score += weigh(params, trace, EvalTerm::BishopTable, sq64 as usize, WHITE, 1);
         }
 
         for sq64 in self.bitboards[Piece::BB as usize].into_iter() {
-            score -= BISHOP_TABLE[MIRROR64[sq64 as usize]];
+            score -= weigh(params, trace, EvalTerm::BishopTable, MIRROR64[sq64 as usize], BLACK, 1);
         }
 
         for sq64 in self.bitboards[Piece::WR as usize].into_iter() {
-            score += ROOK_TABLE[sq64 as usize];
+            score += weigh(params, trace, EvalTerm::RookTable, sq64 as usize, WHITE, 1);
         }
 
         for sq64 in self.bitboards[Piece::BR as usize].into_iter() {
-            score -= ROOK_TABLE[MIRROR64[sq64 as usize]];
+            score -= weigh(params, trace, EvalTerm::RookTable, MIRROR64[sq64 as usize], BLACK, 1);
         }
 
+        // The endgame test uses the fixed piece values tracked by the
+        // board, so that the phase does not change while tuning
         if self.material[BLACK] <= ENDGAME_MATERIAL {
-            score += KING_END_TABLE[self.king_sq[WHITE] as usize];
+            score += weigh(params, trace, EvalTerm::KingEndTable, self.king_sq[WHITE] as usize, WHITE, 1);
         } else {
-            score += KING_OPEN_TABLE[self.king_sq[WHITE] as usize];
+            score += weigh(params, trace, EvalTerm::KingOpenTable, self.king_sq[WHITE] as usize, WHITE, 1);
         }
 
         if self.material[WHITE] <= ENDGAME_MATERIAL {
-            score -= KING_END_TABLE[MIRROR64[self.king_sq[BLACK] as usize]];
+            score -= weigh(params, trace, EvalTerm::KingEndTable, MIRROR64[self.king_sq[BLACK] as usize], BLACK, 1);
         } else {
-            score -= KING_OPEN_TABLE[MIRROR64[self.king_sq[BLACK] as usize]];
+            score -= weigh(params, trace, EvalTerm::KingOpenTable, MIRROR64[self.king_sq[BLACK] as usize], BLACK, 1);
         }
 
         if self.side == WHITE {
@@ -189,6 +470,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn breakdown_matches_evaluate() {
+        for fen in &[START_FEN, "2k1r2r/Bpq3pp/3b4/3Bp3/8/7b/PPP1QP2/R3R1K1 b - - 0 1", "8/8/8/4k3/8/8/8/1NB1K3 b - - 0 1"] {
+            let board = Board::from_fen(fen);
+            let breakdown = board.evaluate_breakdown();
+            assert_eq!(breakdown.score, board.evaluate());
+
+            let total: i32 = breakdown.totals.iter().map(|t| t[WHITE] - t[BLACK]).sum();
+            assert_eq!(if board.side == WHITE { total } else { -total }, breakdown.general);
+        }
+    }
+
     #[test]
     fn pawn_eval() {
         let fen = "2k1r2r/Bpq3pp/3b4/3Bp3/8/7b/PPP1QP2/R3R1K1 w - - 0 1";
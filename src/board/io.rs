@@ -1,9 +1,33 @@
 use crate::board::*;
-use crate::moves::Move;
+use crate::moves::{Move,square_string};
 use crate::pieces::Piece;
 
 impl Board {
     pub fn parse_move(&self, input: &str) -> Option<Move> {
+        // Castling as O-O and O-O-O (xboard in Chess960)
+        let castle_file = match input {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        if let Some(file) = castle_file {
+            let move_list = self.generate_all_moves();
+            return move_list.moves.into_iter().map(|smv| smv.mv).find(|mv| mv.is_castle() && mv.to() % 8 == file);
+        }
+
+        // Crazyhouse drops, as P@e4
+        if let Some((piece, square)) = input.split_once('@') {
+            let mut chars = piece.chars();
+            let piece = match (chars.next(), chars.next()) {
+                (Some(c), None) => Piece::from_char(c.to_ascii_uppercase())?,
+                _ => return None,
+            };
+            let piece = if self.side == WHITE { piece } else { piece.swap() };
+            let move_list = self.generate_all_moves();
+            return move_list.moves.into_iter().map(|smv| smv.mv)
+                .find(|mv| mv.dropped() == piece && square_string(mv.to()) == square.trim_end());
+        }
+
         if input.len() < 4 {
             return None;
         }
@@ -36,8 +60,18 @@ impl Board {
 
         for smv in move_list.moves.into_iter() {
             let mv = smv.mv;
-            if mv.from() == from && mv.to() == to {
-                let prom_piece = mv.promote;
+            if mv.is_castle() {
+                // The king captures its own rook (UCI_Chess960), or
+                // moves to its destination if that can't be mistaken
+                // for a normal king move
+                let king_move = mv.to() == to && (from % 8).abs_diff(to % 8) >= 2;
+                if mv.from() == from && (to == self.castle_rook(mv.to()) || king_move) {
+                    return Some(mv);
+                }
+                continue;
+            }
+            if mv.from() == from && mv.to() == to && ! mv.is_drop() {
+                let prom_piece = mv.promote();
                 if prom_piece.exists() {
                     // Instead of panicking, return None if the input
                     // is missing a promotion character
@@ -47,6 +81,7 @@ impl Board {
                         Piece::WB | Piece::BB if c4 == 'b' => { return Some(mv); }
                         Piece::WQ | Piece::BQ if c4 == 'q' => { return Some(mv); }
                         Piece::WN | Piece::BN if c4 == 'n' => { return Some(mv); }
+                        Piece::WK | Piece::BK if c4 == 'k' => { return Some(mv); }
                         _ => continue,
                     }
                 } else {
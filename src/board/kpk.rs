@@ -0,0 +1,265 @@
+// King and pawn versus king bitbase.
+//
+// Every KPK position is classified as a win or draw for the side with
+// the pawn by retrograde analysis: starting from the positions that
+// are immediately won (safe promotion) or drawn (stalemate, or the
+// pawn is captured), positions are repeatedly resolved from the
+// results of their successors until nothing changes.  Positions that
+// are never resolved as wins are draws.
+//
+// Positions are stored with a white pawn on files A to D; other
+// positions are flipped to match.  This gives 24 pawn squares, 64
+// squares for each king, and two sides to move.
+
+use crate::board::*;
+use crate::pieces::{KING_MOVES,WHITE_PAWN_MOVES};
+
+const NUM_PAWN_SQUARES: usize = 24;
+const NUM_POSITIONS: usize = NUM_PAWN_SQUARES * 64 * 64 * 2;
+
+lazy_static! {
+    // One bit per position, set for wins
+    static ref KPK_BITBASE: Vec<u64> = generate();
+}
+
+// Not necessary as the lazy static is automatically initialized, but
+// generation takes a noticeable amount of time, so it is better done
+// when the program starts
+pub fn init_kpk() {
+    lazy_static::initialize(&KPK_BITBASE);
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+// Pawn must be on files A to D, and ranks 2 to 7
+fn index(side: usize, white_king: Square, black_king: Square, pawn: Square) -> usize {
+    let pawn_index = (pawn / 8 - RANK_2) as usize * 4 + (pawn % 8) as usize;
+    ((pawn_index * 64 + white_king as usize) * 64 + black_king as usize) * 2 + side
+}
+
+fn pawn_square(pawn_index: usize) -> Square {
+    fr_to_sq((pawn_index % 4) as Square, (pawn_index / 4) as Square + RANK_2)
+}
+
+fn attacks(bb: Bitboard, sq: Square) -> bool {
+    bb.0 & (1 << sq) != 0
+}
+
+// Classify a position without looking at its successors
+fn initial_result(side: usize, white_king: Square, black_king: Square, pawn: Square) -> KpkResult {
+    if white_king == black_king || white_king == pawn || black_king == pawn {
+        return KpkResult::Invalid;
+    }
+    if attacks(KING_MOVES[white_king as usize], black_king) {
+        return KpkResult::Invalid;
+    }
+    // Black can't be in check with white to move
+    if side == WHITE && attacks(WHITE_PAWN_MOVES[pawn as usize], black_king) {
+        return KpkResult::Invalid;
+    }
+
+    let push = pawn + 8;
+    if side == WHITE {
+        // Promote without losing the new queen
+        if pawn / 8 == RANK_7 && white_king != push && black_king != push
+            && (! attacks(KING_MOVES[black_king as usize], push) || attacks(KING_MOVES[white_king as usize], push)) {
+            return KpkResult::Win;
+        }
+    } else {
+        let guarded = KING_MOVES[white_king as usize].0 | WHITE_PAWN_MOVES[pawn as usize].0;
+        if KING_MOVES[black_king as usize].0 & ! guarded == 0 {
+            // Stalemate, or mate if in check
+            if attacks(WHITE_PAWN_MOVES[pawn as usize], black_king) {
+                return KpkResult::Win;
+            }
+            return KpkResult::Draw;
+        }
+        // Capture an undefended pawn
+        if attacks(KING_MOVES[black_king as usize], pawn) && ! attacks(KING_MOVES[white_king as usize], pawn) {
+            return KpkResult::Draw;
+        }
+    }
+
+    KpkResult::Unknown
+}
+
+// Classify a position from the results of its successors
+fn classify(db: &[KpkResult], side: usize, white_king: Square, black_king: Square, pawn: Square) -> KpkResult {
+    // Results that are good and bad for the side to move
+    let (good, bad) = if side == WHITE { (KpkResult::Win, KpkResult::Draw) } else { (KpkResult::Draw, KpkResult::Win) };
+
+    let mut all_bad = true;
+    let mut check = |result: KpkResult| -> bool {
+        if result == good {
+            return true;
+        }
+        if result != KpkResult::Invalid && result != bad {
+            all_bad = false;
+        }
+        false
+    };
+
+    if side == WHITE {
+        for to in KING_MOVES[white_king as usize] {
+            if check(db[index(BLACK, to, black_king, pawn)]) {
+                return good;
+            }
+        }
+        // Promotions are handled by initial_result
+        let push = pawn + 8;
+        if pawn / 8 < RANK_7 && push != white_king && push != black_king {
+            if check(db[index(BLACK, white_king, black_king, push)]) {
+                return good;
+            }
+            let double_push = push + 8;
+            if pawn / 8 == RANK_2 && double_push != white_king && double_push != black_king
+                && check(db[index(BLACK, white_king, black_king, double_push)]) {
+                return good;
+            }
+        }
+    } else {
+        // Illegal king moves lead to invalid positions
+        for to in KING_MOVES[black_king as usize] {
+            if to != pawn && check(db[index(WHITE, white_king, to, pawn)]) {
+                return good;
+            }
+        }
+    }
+
+    if all_bad { bad } else { KpkResult::Unknown }
+}
+
+fn generate() -> Vec<u64> {
+    let mut db = vec![KpkResult::Invalid; NUM_POSITIONS];
+    let mut positions = Vec::new();
+
+    for pawn_index in 0..NUM_PAWN_SQUARES {
+        let pawn = pawn_square(pawn_index);
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for &side in &[WHITE, BLACK] {
+                    let result = initial_result(side, white_king, black_king, pawn);
+                    db[index(side, white_king, black_king, pawn)] = result;
+                    if result == KpkResult::Unknown {
+                        positions.push((side, white_king, black_king, pawn));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(side, white_king, black_king, pawn) in &positions {
+            let i = index(side, white_king, black_king, pawn);
+            if db[i] == KpkResult::Unknown {
+                db[i] = classify(&db, side, white_king, black_king, pawn);
+                changed |= db[i] != KpkResult::Unknown;
+            }
+        }
+    }
+
+    let mut bitbase = vec![0u64; NUM_POSITIONS / 64];
+    for (i, &result) in db.iter().enumerate() {
+        if result == KpkResult::Win {
+            bitbase[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bitbase
+}
+
+// Whether the side with the pawn wins, with the pawn moving up the
+// board.  The position must be legal.
+pub fn probe(side: usize, white_king: Square, black_king: Square, pawn: Square) -> bool {
+    // Flip files E to H onto A to D
+    let flip = if pawn % 8 > 3 { 7 } else { 0 };
+    let i = index(side, white_king ^ flip, black_king ^ flip, pawn ^ flip);
+    KPK_BITBASE[i / 64] & (1 << (i % 64)) != 0
+}
+
+impl Board {
+    // For KPK positions, return whether the side with the pawn wins
+    pub fn probe_kpk(&self) -> Option<bool> {
+        if self.bb_sides[BOTH].count() != 3 || self.variant != Variant::Standard {
+            return None;
+        }
+        let (strong, pawn) = if self.bitboards[Piece::WP as usize].nonzero() {
+            (WHITE, self.bitboards[Piece::WP as usize].clone().pop_bit())
+        } else if self.bitboards[Piece::BP as usize].nonzero() {
+            (BLACK, self.bitboards[Piece::BP as usize].clone().pop_bit())
+        } else {
+            return None;
+        };
+
+        // Flip ranks so that the strong side is white
+        let flip = if strong == WHITE { 0 } else { 56 };
+        let side = if self.side == strong { WHITE } else { BLACK };
+        let pawn = pawn ^ flip;
+        // A pawn on the first or last rank can only come from a FEN,
+        // and isn't in the table
+        if pawn / 8 < RANK_2 || pawn / 8 > RANK_7 {
+            return None;
+        }
+        Some(probe(side, self.king_sq[strong] ^ flip, self.king_sq[strong^1] ^ flip, pawn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts of legal positions and wins with the given side to move,
+    // over all 48 pawn squares
+    fn statistics(side: usize) -> (u32, u32) {
+        let (mut legal, mut wins) = (0, 0);
+        for pawn_index in 0..NUM_PAWN_SQUARES {
+            let pawn = pawn_square(pawn_index);
+            for white_king in 0..64 {
+                for black_king in 0..64 {
+                    if initial_result(side, white_king, black_king, pawn) == KpkResult::Invalid {
+                        continue;
+                    }
+                    legal += 2;
+                    if probe(side, white_king, black_king, pawn) {
+                        wins += 2;
+                    }
+                }
+            }
+        }
+        (legal, wins)
+    }
+
+    #[test]
+    fn known_statistics() {
+        assert_eq!(statistics(WHITE), (163328, 124960));
+        assert_eq!(statistics(BLACK), (168024, 97604));
+    }
+
+    #[test]
+    fn probe_positions() {
+        // Black is stalemated if it has to move
+        assert_eq!(Board::from_fen("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1").probe_kpk(), Some(true));
+        assert_eq!(Board::from_fen("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1").probe_kpk(), Some(false));
+        // Same positions for black, flipped and mirrored
+        assert_eq!(Board::from_fen("8/8/8/8/8/3k4/3p4/3K4 b - - 0 1").probe_kpk(), Some(true));
+        assert_eq!(Board::from_fen("8/8/8/8/8/3k4/3p4/3K4 w - - 0 1").probe_kpk(), Some(false));
+        // Rook pawn with the defending king in front
+        assert_eq!(Board::from_fen("k7/8/8/8/8/8/P7/7K w - - 0 1").probe_kpk(), Some(false));
+        assert_eq!(Board::from_fen(START_FEN).probe_kpk(), None);
+
+        // Pawns that can't move aren't in the table
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/P3K3 w - - 0 1");
+        assert_eq!(board.probe_kpk(), None);
+        board.search(&mut SearchInfo::new(2));
+        assert_eq!(Board::from_fen("p3k3/8/8/8/8/8/8/4K3 w - - 0 1").probe_kpk(), None);
+    }
+}
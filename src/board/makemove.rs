@@ -13,34 +13,33 @@ impl Board {
         debug_assert!(square_on_board(from));
         debug_assert!(square_on_board(to));
         debug_assert!(side_valid(side));
-        debug_assert!(self.pieces[from as usize].exists());
+        debug_assert!(mv.is_drop() || self.pieces[from as usize].exists());
+
+        // Kings can't capture in atomic chess, as they would explode
+        if self.variant == Variant::Atomic && mv.is_capture() && self.pieces[from as usize].is_king() {
+            return false;
+        }
 
         let prev_hash = self.hash;
 
+        let promoted = self.promoted;
+
         if mv.is_en_pas() {
-            if side == WHITE {
+            let captured = if side == WHITE {
                 // Todo: use a NORTH constant
-                self.clear_piece(to - 8);
+                to - 8
             } else {
-                self.clear_piece(to + 8);
+                to + 8
+            };
+            if self.variant == Variant::Crazyhouse {
+                self.add_to_pocket(self.pocket_piece(captured));
             }
+            self.clear_piece(captured);
         }
         else if mv.is_castle() {
-            if to == Position::C1 as Square {
-                self.move_piece(Position::A1 as Square, Position::D1 as Square);
-            }
-            else if to == Position::C8 as Square {
-                self.move_piece(Position::A8 as Square, Position::D8 as Square);
-            }
-            else if to == Position::G1 as Square {
-                self.move_piece(Position::H1 as Square, Position::F1 as Square);
-            }
-            else if to == Position::G8 as Square {
-                self.move_piece(Position::H8 as Square, Position::F8 as Square);
-            }
-            else {
-                panic!("invalid to position in move with castle flag");
-            }
+            // The rook is taken off first, as the king may move to its
+            // square in Chess960, and put back after the king has moved
+            self.clear_piece(self.castle_rook(to));
         }
 
         if self.en_pas != Position::NONE as Square {
@@ -55,13 +54,16 @@ impl Board {
             en_pas: self.en_pas,
             castle_perm: self.castle_perm,
             hash: prev_hash,
+            checks: self.checks,
+            exploded: Vec::new(),
+            promoted: promoted,
         };
 
         // Todo: verify this can just go at the end...
         self.history.push(undo);
 
-        self.castle_perm &= CASTLE_PERM[from as usize];
-        self.castle_perm &= CASTLE_PERM[to as usize];
+        self.castle_perm &= self.castle_mask[from as usize];
+        self.castle_perm &= self.castle_mask[to as usize];
         self.en_pas = Position::NONE as Square;
             
         // Hash in new state of castling permission
@@ -70,14 +72,22 @@ impl Board {
         self.fifty_move += 1;
 
         if mv.is_capture() {
+            if self.variant == Variant::Crazyhouse {
+                self.add_to_pocket(self.pocket_piece(to));
+            }
             self.clear_piece(to);
             self.fifty_move = 0;
+            // Only possible in atomic chess, next to the own king,
+            // which explodes as well
+            if mv.capture().is_king() {
+                self.king_sq[side^1] = Position::NONE as Square;
+            }
         }
 
         self.ply += 1;
         self.hist_ply += 1;
 
-        if self.pieces[from as usize].is_pawn() {
+        if self.pieces[from as usize].is_pawn() || mv.dropped().is_pawn() {
             self.fifty_move = 0;
             if mv.is_pawn_start() {
                 if side == WHITE {
@@ -92,31 +102,74 @@ impl Board {
             }
         }
 
-        self.move_piece(from, to);
+        if mv.is_drop() {
+            self.remove_from_pocket(mv.dropped());
+            self.add_piece(mv.dropped(), to);
+        } else {
+            self.move_piece(from, to);
+        }
+
+        if mv.is_castle() {
+            let rook_to = if to % 8 == 6 { to - 1 } else { to + 1 };
+            self.add_piece(if side == WHITE { Piece::WR } else { Piece::BR }, rook_to);
+        }
 
         if mv.is_promotion() {
-            debug_assert!(mv.promote.exists() && ! mv.promote.is_pawn());
+            debug_assert!(mv.promote().exists() && ! mv.promote().is_pawn());
             self.clear_piece(to);
-            self.add_piece(mv.promote, to);
+            self.add_piece(mv.promote(), to);
+            self.promoted.set_bit(to);
         }
 
         if self.pieces[to as usize].is_king() {
             self.king_sq[self.side] = to;
         }
 
+        if self.variant == Variant::Atomic && (mv.is_capture() || mv.is_en_pas()) {
+            let exploded = self.explode(to);
+            self.history.last_mut().unwrap().exploded = exploded;
+        }
+
         self.side ^= 1;
         self.hash_side();
 
         debug_assert!(self.check());
 
-        if self.square_attacked(self.king_sq[side], self.side) {
+        if self.king_attacked(side) {
             self.undo_move();
             return false;
         }
-        
+
+        if self.variant == Variant::ThreeCheck && self.in_check() {
+            self.hash_checks();
+            self.checks[side] += 1;
+            self.hash_checks();
+        }
+
         true
     }
 
+    // Remove the piece on sq and all pieces other than pawns around
+    // it, returning them for undo_move
+    fn explode(&mut self, sq: Square) -> Vec<(Square, Piece)> {
+        let mut exploded = vec![(sq, self.pieces[sq as usize])];
+        exploded.extend(KING_MOVES[sq as usize].into_iter()
+                        .map(|around| (around, self.pieces[around as usize]))
+                        .filter(|(_, piece)| piece.exists() && ! piece.is_pawn()));
+
+        self.hash_castle();
+        for &(sq, piece) in &exploded {
+            self.clear_piece(sq);
+            self.castle_perm &= self.castle_mask[sq as usize];
+            if piece.is_king() {
+                self.king_sq[piece.color()] = Position::NONE as Square;
+            }
+        }
+        self.hash_castle();
+
+        exploded
+    }
+
     pub fn undo_move(&mut self) {
         debug_assert!(self.check());
 
@@ -140,6 +193,10 @@ impl Board {
         self.fifty_move = undo.fifty_move;
         self.en_pas = undo.en_pas;
 
+        self.hash_checks();
+        self.checks = undo.checks;
+        self.hash_checks();
+
         if self.en_pas != Position::NONE as Square {
             self.hash_en_pas();
         }
@@ -148,6 +205,21 @@ impl Board {
         self.side ^= 1;
         self.hash_side();
 
+        if mv.is_drop() {
+            self.clear_piece(to);
+            self.add_to_pocket(mv.dropped());
+            self.promoted = undo.promoted;
+            debug_assert!(self.check());
+            return;
+        }
+
+        for &(sq, piece) in &undo.exploded {
+            self.add_piece(piece, sq);
+            if piece.is_king() {
+                self.king_sq[piece.color()] = sq;
+            }
+        }
+
         if mv.is_en_pas() {
             if self.side == WHITE {
                 // Todo: Use a NORTH constant
@@ -155,22 +227,12 @@ impl Board {
             } else {
                 self.add_piece(Piece::WP, to+8);
             }
-        } else if mv.is_castle() {
-            if to == Position::C1 as Square {
-                self.move_piece(Position::D1 as Square, Position::A1 as Square);
-            }
-            else if to == Position::C8 as Square {
-                self.move_piece(Position::D8 as Square, Position::A8 as Square);
-            }
-            else if to == Position::G1 as Square {
-                self.move_piece(Position::F1 as Square, Position::H1 as Square);
-            }
-            else if to == Position::G8 as Square {
-                self.move_piece(Position::F8 as Square, Position::H8 as Square);
-            }
-            else {
-                panic!("invalid to position in undo move with castle flag");
+            if self.variant == Variant::Crazyhouse {
+                self.remove_from_pocket(if self.side == WHITE { Piece::WP } else { Piece::BP });
             }
+        } else if mv.is_castle() {
+            let rook_to = if to % 8 == 6 { to - 1 } else { to + 1 };
+            self.clear_piece(rook_to);
         }
 
         self.move_piece(to, from);
@@ -179,16 +241,30 @@ impl Board {
             self.king_sq[self.side] = from;
         }
 
+        if mv.is_castle() {
+            let rook = if self.side == WHITE { Piece::WR } else { Piece::BR };
+            self.add_piece(rook, self.castle_rook(to));
+        }
+
         if mv.is_capture() {
-            self.add_piece(mv.capture, to);
+            self.add_piece(mv.capture(), to);
+            if self.variant == Variant::Crazyhouse {
+                self.promoted = undo.promoted;
+                self.remove_from_pocket(self.pocket_piece(to));
+            }
+            if mv.capture().is_king() {
+                self.king_sq[self.side^1] = to;
+            }
         }
 
         if mv.is_promotion() {
-            debug_assert!(mv.promote.exists() && ! mv.promote.is_pawn());
+            debug_assert!(mv.promote().exists() && ! mv.promote().is_pawn());
             self.clear_piece(from);
-            self.add_piece(if mv.promote.color() == WHITE { Piece::WP } else { Piece::BP }, from)
+            self.add_piece(if mv.promote().color() == WHITE { Piece::WP } else { Piece::BP }, from)
         }
 
+        self.promoted = undo.promoted;
+
         debug_assert!(self.check());
     }
     
@@ -200,8 +276,12 @@ impl Board {
         let color = piece.color();
 
         self.hash_piece(piece, sq);
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.remove(piece, sq);
+        }
 
         self.pieces[sq as usize] = Piece::Empty;
+        self.promoted.clear_bit(sq);
         self.material[color] -= piece.value();
 
 
@@ -226,6 +306,9 @@ impl Board {
         let color = piece.color();
 
         self.hash_piece(piece, sq);
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.add(piece, sq);
+        }
         self.pieces[sq as usize] = piece;
 
         if piece.is_big() {
@@ -256,6 +339,16 @@ impl Board {
         self.hash_piece(piece, to);
         self.pieces[to as usize] = piece;
 
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.remove(piece, from);
+            nnue.add(piece, to);
+        }
+
+        if self.promoted.0 & (1 << from) != 0 {
+            self.promoted.clear_bit(from);
+            self.promoted.set_bit(to);
+        }
+
         self.bitboards[piece as usize].clear_bit(from);
         self.bitboards[piece as usize].set_bit(to);
         self.bb_sides[color].clear_bit(from);
@@ -279,15 +372,12 @@ impl Board {
     fn hash_castle(&mut self) {
         self.hash ^= self.hash_keys.castle_keys[self.castle_perm as usize];
     }
-}
 
-const CASTLE_PERM: [u8; BOARD_SQ_NUM] = [
-    13, 15, 15, 15, 12, 15, 15, 14,
-    15, 15, 15, 15, 15, 15, 15, 15,
-    15, 15, 15, 15, 15, 15, 15, 15,
-    15, 15, 15, 15, 15, 15, 15, 15,
-    15, 15, 15, 15, 15, 15, 15, 15,
-    15, 15, 15, 15, 15, 15, 15, 15,
-    15, 15, 15, 15, 15, 15, 15, 15,
-     7, 15, 15, 15,  3, 15, 15, 11,
-];
+    fn hash_checks(&mut self) {
+        for color in [WHITE, BLACK] {
+            if self.checks[color] > 0 {
+                self.hash ^= self.hash_keys.check_keys[color][self.checks[color] as usize];
+            }
+        }
+    }
+}
@@ -5,20 +5,51 @@ mod io;
 mod search;
 mod evaluate;
 mod uci;
+mod nnue;
+mod endgame;
+mod kpk;
+mod timeman;
+mod clock;
+mod observer;
+mod searchthread;
+mod adjudicate;
+mod book;
+mod random64;
+mod san;
+mod zobrist;
+mod castling;
+mod variant;
+mod crazyhouse;
+mod antichess;
+mod position;
+mod status;
+mod draw;
 
-use rand::Rng;
 use std::collections::HashMap;
 use std::fmt;
 
 use crate::pieces::*;
 use crate::bitboard::{self,Bitboard};
 use crate::moves;
-use crate::version::PROGRAM_NAME;
 use evaluate::MIRROR64;
 
-pub use search::{SearchInfo,GameMode,benchmark_search};
+pub use search::{SearchInfo,SearchProgress,benchmark_search};
+pub use searchthread::SearchHandle;
+pub use observer::{SearchObserver,Iteration,SearchResult,SilentObserver,UciObserver,XboardObserver,ConsoleObserver};
+pub use clock::{Clock,SystemClock,NodeClock};
+pub use adjudicate::{Adjudicator,Decision};
+pub use book::{Book,BookEntry,BookSelection,BookOptions};
 pub use uci::uci_loop;
 pub use movegen::init_mvv_lva;
+pub use nnue::Network;
+pub use endgame::{Endgame,EndgameKind,init_endgames};
+pub use kpk::init_kpk;
+pub use zobrist::{set_zobrist_seed,zobrist_seed,DEFAULT_ZOBRIST_SEED};
+pub use castling::{CastleNotation,MoveFormat};
+pub use variant::Variant;
+pub use position::PositionState;
+pub use status::GameStatus;
+pub use evaluate::{EvalParams,EvalTerm,EvalTrace,EvalBreakdown,NoTrace,DEFAULT_EVAL_PARAMS};
 
 // usize is used to avoid need for conversion with array access
 pub type Square = u8;
@@ -54,6 +85,10 @@ impl Castling {
     pub const BQ: u8 = 8;
 }
 
+// Rook squares for standard castling, in the order of the castling
+// permission bits
+const STANDARD_CASTLE_ROOKS: [Square; 4] = [Position::H1 as Square, Position::A1 as Square, Position::H8 as Square, Position::A8 as Square];
+
 pub const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 pub fn fr_to_sq(file: FileRank, rank: FileRank) -> Square {
@@ -68,14 +103,21 @@ pub fn square_on_board(sq: Square) -> bool {
     sq <= 63
 }
 
+#[derive(Clone)]
 pub struct Undo {
     mv: moves::Move,
     castle_perm: u8,
     en_pas: Square,
     fifty_move: u32,
     hash: u64,
+    checks: [u8; 2],
+    // Pieces removed by an explosion in atomic chess, starting with
+    // the capturing piece
+    exploded: Vec<(Square, Piece)>,
+    promoted: Bitboard,
 }
 
+#[derive(Clone)]
 pub struct Board {
     pub pieces: [Piece; BOARD_SQ_NUM],
 
@@ -96,13 +138,32 @@ pub struct Board {
 
     ply: u32,
     hist_ply: u32,
+    // Half moves played before the position the board was set up
+    // with, from the full move number in the FEN
+    start_ply: u32,
 
     pub history: Vec<Undo>,
 
     pub castle_perm: u8,
+    // Starting squares of the rooks for each castling right (WK, WQ,
+    // BK, BQ), which vary in Chess960
+    castle_rooks: [Square; 4],
+    // Castling rights kept after a move from or to each square
+    castle_mask: [u8; BOARD_SQ_NUM],
     hash: u64,
 
-    hash_keys: HashKeys,
+    variant: Variant,
+    // Checks given by each side, for Three-check
+    checks: [u8; 2],
+    // Pieces in hand and squares of promoted pieces, for Crazyhouse
+    pockets: [u8; NUM_PIECE_TYPES_BOTH],
+    promoted: Bitboard,
+
+    // Shared by all boards
+    hash_keys: &'static zobrist::HashKeys,
+
+    // Neural network evaluation, if enabled
+    nnue: Option<nnue::Nnue>,
 
     pub pv_table: HashMap<u64, moves::Move>,
     // Todo: better as a member or a return value?
@@ -139,13 +200,23 @@ impl Board {
 
             ply: 0,
             hist_ply: 0,
+            start_ply: 0,
 
             history: Vec::new(),
 
             castle_perm: 0,
+            castle_rooks: STANDARD_CASTLE_ROOKS,
+            castle_mask: [15; BOARD_SQ_NUM],
             hash: 0,
 
-            hash_keys: HashKeys::new(),
+            variant: Variant::Standard,
+            checks: [0; 2],
+            pockets: [0; NUM_PIECE_TYPES_BOTH],
+            promoted: Bitboard::new(),
+
+            hash_keys: zobrist::hash_keys(),
+
+            nnue: None,
 
             // Todo: VICE uses a reset board function, and the
             // pv_table map is not re-initialized when the board is
@@ -166,108 +237,125 @@ impl Board {
         board
     }
 
+    // Panics if the FEN string is malformed
     pub fn from_fen(fen: &str) -> Board {
-        let mut board = Board::new();
-
-        let mut rank = RANK_8;
-        let mut file = FILE_A;
-        let mut piece;
-        let mut count;
-        let mut sq: Square;
-
-        let mut fen_iter = fen.chars();
-        let mut c;
-
-        // This is essentially just a while(true) loop that must be
-        // broken out off
-        while rank >= RANK_1 {
-            c = fen_iter.next().unwrap();
-            count = 1;
-            match c {
-                'p' => piece = Piece::BP,
-                'r' => piece = Piece::BR,
-                'n' => piece = Piece::BN,
-                'b' => piece = Piece::BB,
-                'k' => piece = Piece::BK,
-                'q' => piece = Piece::BQ,
-
-                'P' => piece = Piece::WP,
-                'R' => piece = Piece::WR,
-                'N' => piece = Piece::WN,
-                'B' => piece = Piece::WB,
-                'K' => piece = Piece::WK,
-                'Q' => piece = Piece::WQ,
-
-                '1'..='8' => {
-                    piece = Piece::Empty;
-                    count = c.to_digit(10).unwrap();
-                }, 
-
-                '/' | ' ' => {
-                    file = FILE_A;
-                    if rank <= RANK_1 {
-                        break;
-                    }
-                    rank -= 1;
-                    continue;
-                },
-                
-                _ => panic!("FEN error"),
-            }
+        Board::try_from_fen(fen).expect("FEN error")
+    }
 
-            for _i in 0..count {
-                if piece.exists() {
-                    sq = fr_to_sq(file, rank);
-                    board.pieces[sq as usize] = piece;
+    // Returns None if the FEN string is malformed, for FENs from
+    // outside the program, such as PGN tags
+    pub fn try_from_fen(fen: &str) -> Option<Board> {
+        let mut board = Board::new();
+        let mut fields = fen.split_whitespace();
+
+        // Crazyhouse pocket, as [Qn] after the pieces or as a ninth
+        // rank, /Qn
+        let placement = fields.next()?;
+        let (placement, mut pocket) = match placement.find('[') {
+            Some(i) => (&placement[..i], Some(placement[i+1..].strip_suffix(']')?)),
+            None => (placement, None),
+        };
+        let mut rows: Vec<&str> = placement.split('/').collect();
+        if rows.len() == 9 && pocket.is_none() {
+            pocket = rows.pop();
+        }
+        if rows.len() != 8 {
+            return None;
+        }
+
+        for (row, rank) in rows.iter().zip(RANKS_ITER.rev()) {
+            let mut file = FILE_A;
+            for c in row.chars() {
+                match c {
+                    '1'..='8' => file += c.to_digit(10).unwrap() as FileRank,
+
+                    // Promoted piece in Crazyhouse
+                    '~' => {
+                        if file == FILE_A || ! board.pieces[fr_to_sq(file - 1, rank) as usize].exists() {
+                            return None;
+                        }
+                        board.promoted.set_bit(fr_to_sq(file - 1, rank));
+                    },
+
+                    _ => {
+                        if file > FILE_H {
+                            return None;
+                        }
+                        board.pieces[fr_to_sq(file, rank) as usize] = Piece::from_char(c)?;
+                        file += 1;
+                    },
                 }
-                file += 1;
+                if file > FILE_H + 1 {
+                    return None;
+                }
+            }
+            if file != FILE_H + 1 {
+                return None;
             }
         }
+        if let Some(pocket) = pocket {
+            board.parse_pocket(pocket);
+        }
 
-        c = fen_iter.next().unwrap();
-        board.side = match c {
-            'w' => WHITE,
-            'b' => BLACK,
-            _ => panic!("unexpected FEN side color character"),
+        board.side = match fields.next()? {
+            "w" => WHITE,
+            "b" => BLACK,
+            _ => return None,
         };
 
-        // Castling permissions:
-        fen_iter.next();
-        c = fen_iter.next().unwrap();
-        for _i in 0..4 {
-            match c {
-                'K' => board.castle_perm |= Castling::WK,
-                'Q' => board.castle_perm |= Castling::WQ,
-                'k' => board.castle_perm |= Castling::BK,
-                'q' => board.castle_perm |= Castling::BQ,
-                '-' => (),
-                ' ' => break,
-                _ => panic!("unexpected FEN castling permission character"),
+        // Castling permissions, as in standard FEN, X-FEN (KQkq for
+        // the outermost rook, or the file of the rook) or Shredder-FEN
+        // (always the file of the rook)
+        let castling = fields.next()?;
+        if castling != "-" {
+            for c in castling.chars() {
+                if ! board.add_castle_right(c) {
+                    return None;
+                }
             }
-            c = fen_iter.next().unwrap();
         }
-
-        // En passant
-        c = fen_iter.next().unwrap();
-        if c != '-' {
-            file = c as FileRank - 'a' as FileRank;
-            c = fen_iter.next().unwrap();
-            rank = c as FileRank - '1' as FileRank;
-            assert!(file >= FILE_A && file <= FILE_H);
-            assert!(rank >= RANK_1 && rank <= RANK_8);
+        board.set_castle_mask();
+
+        // En passant, which must be behind a pawn of the side that
+        // just moved
+        let en_pas = fields.next()?;
+        if en_pas != "-" {
+            let mut chars = en_pas.chars();
+            let (file, rank) = match (chars.next(), chars.next(), chars.next()) {
+                (Some(file @ 'a'..='h'), Some(rank @ '1'..='8'), None) => (file as FileRank - b'a', rank as FileRank - b'1'),
+                _ => return None,
+            };
+            if rank != if board.side == WHITE { RANK_6 } else { RANK_3 } {
+                return None;
+            }
             board.en_pas = fr_to_sq(file, rank);
         }
 
+        // Three-check counts, if given, then the half move clock and
+        // the full move number
+        let mut numbers = Vec::new();
+        for field in fields {
+            if let Some(checks) = variant::parse_checks(field) {
+                board.checks = checks;
+            } else if let Ok(number) = field.parse::<u32>() {
+                numbers.push(number);
+            }
+        }
+        board.fifty_move = numbers.first().copied().unwrap_or(0);
+        let full_move = numbers.get(1).copied().unwrap_or(1).max(1);
+        board.start_ply = 2 * (full_move - 1) + board.side as u32;
+
         board.hash = board.get_position_hash();
 
         board.update_lists_and_material();
 
-        board
+        Some(board)
     }
 
     // Moves the current board into a new board with the given FEN string
     //
-    // The only information retained is the pv_table
+    // The only information retained is the variant, the pv_table and
+    // the evaluation network
     //
     // An alternative would be to separate out parse_fen into a member
     // function and implement a reset function, but then there is some
@@ -275,10 +363,76 @@ impl Board {
     // and new()
     pub fn update_from_fen(self, fen: &str) -> Board {
         let mut board = Board::from_fen(fen);
+        board.set_variant(self.variant);
+        board.set_network(self.network());
         board.pv_table = self.pv_table;
         board
     }
 
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (RANK_1..=RANK_8).rev() {
+            let mut empty = 0;
+            for file in FILE_A..=FILE_H {
+                let piece = self.pieces[fr_to_sq(file, rank) as usize];
+                if piece.exists() {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push_str(&piece.to_string());
+                    if self.variant == Variant::Crazyhouse && self.promoted.0 & (1 << fr_to_sq(file, rank)) != 0 {
+                        fen.push('~');
+                    }
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank != RANK_1 {
+                fen.push('/');
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            fen.push_str(&self.pocket_fen());
+        }
+
+        fen.push_str(if self.side == WHITE { " w " } else { " b " });
+
+        // X-FEN, which is the same as standard FEN for standard
+        // castling rights
+        if self.castle_perm == 0 {
+            fen.push('-');
+        }
+        for i in 0..4 {
+            if self.castle_perm & (1 << i) != 0 {
+                fen.push(self.castle_char(i));
+            }
+        }
+
+        if self.en_pas == Position::NONE as Square {
+            fen.push_str(" -");
+        } else {
+            fen.push_str(&format!(" {}{}", (b'a' + self.en_pas % 8) as char, (b'1' + self.en_pas / 8) as char));
+        }
+
+        if self.variant == Variant::ThreeCheck {
+            fen.push(' ');
+            fen.push_str(&self.checks_fen());
+        }
+
+        fen.push_str(&format!(" {} {}", self.fifty_move, self.move_number()));
+        fen
+    }
+
+    // Full move number, which starts at 1 and is incremented after
+    // black's move
+    pub fn move_number(&self) -> u32 {
+        (self.start_ply + self.hist_ply) / 2 + 1
+    }
+
     pub fn get_position_hash(&self) -> u64 {
         let mut hash: u64 = 0;
 
@@ -299,7 +453,17 @@ impl Board {
         }
 
         hash ^= self.hash_keys.castle_keys[self.castle_perm as usize];
-        
+
+        for color in [WHITE, BLACK] {
+            if self.checks[color] > 0 {
+                hash ^= self.hash_keys.check_keys[color][self.checks[color] as usize];
+            }
+        }
+
+        for piece in PIECE_TYPES.iter() {
+            hash ^= self.pocket_hash(*piece);
+        }
+
         hash
     }
 
@@ -391,15 +555,28 @@ impl Board {
 
         assert!(self.side == WHITE || self.side == BLACK);
         assert_eq!(self.hash, self.get_position_hash());
+        assert!(self.check_nnue());
 
         assert!(self.en_pas == Position::NONE as Square ||
                 (self.en_pas/8 == RANK_6 && self.side == WHITE) ||
                 (self.en_pas/8 == RANK_3 && self.side == BLACK));
 
-        assert_eq!(self.pieces[self.king_sq[WHITE] as usize], Piece::WK);
-        assert_eq!(self.pieces[self.king_sq[BLACK] as usize], Piece::BK);
-        assert_eq!(self.king_sq[WHITE], self.bitboards[Piece::WK as usize].clone().pop_bit());
-        assert_eq!(self.king_sq[BLACK], self.bitboards[Piece::BK as usize].clone().pop_bit());
+        // Any number of kings in antichess, where king_sq isn't used
+        if self.variant != Variant::Antichess {
+            for (color, king) in [(WHITE, Piece::WK), (BLACK, Piece::BK)] {
+                if self.bitboards[king as usize].nonzero() {
+                    assert_eq!(self.pieces[self.king_sq[color] as usize], king);
+                    assert_eq!(self.king_sq[color], self.bitboards[king as usize].clone().pop_bit());
+                } else {
+                    // Only after an explosion
+                    assert_eq!(self.variant, Variant::Atomic);
+                    assert_eq!(self.king_sq[color], Position::NONE as Square);
+                }
+            }
+        }
+
+        // Promoted pieces must be on the board
+        assert_eq!(self.promoted.0 & ! self.bb_sides[BOTH].0, 0);
 
         // Check side piece bitboards:
         assert_eq!(self.bb_sides[WHITE].count(), PIECE_TYPES.iter().filter(|p| p.color()==WHITE).map(|&p| self.bitboards[p as usize].count()).sum());
@@ -409,10 +586,16 @@ impl Board {
     }
 
     pub fn square_attacked(&self, sq: Square, side: usize) -> bool {
+        debug_assert!(self.check());
+        self.square_attacked_with(sq, side, self.bb_sides[BOTH].0)
+    }
+
+    // Whether side attacks sq, with sliding pieces blocked by the
+    // given occupancy
+    fn square_attacked_with(&self, sq: Square, side: usize, occ: u64) -> bool {
         debug_assert!(square_on_board(sq));
         debug_assert!(side_valid(side));
-        debug_assert!(self.check());
-        
+
         let mut piece;
 
         // pawns
@@ -439,7 +622,6 @@ impl Board {
 
         let mut sq_bb = Bitboard::new();
         sq_bb.set_bit(sq);
-        let occ = self.bb_sides[BOTH].0;
 
         // bishops or queens
         let bishop_queens = match side {
@@ -475,82 +657,36 @@ impl Board {
         false
     }
 
-    fn repetition_count(&self) -> u32 {
-        let mut repetitions = 0;
-        for item in &self.history {
-            if item.hash == self.hash {
-                repetitions += 1;
-            }
-        }
-        repetitions
-    }
-
-    // Checks whether the position is a draw because neither side can
-    // give mate
-    fn is_draw_by_material(&self) -> bool {
-        if self.bitboards[Piece::WP as usize].count() > 0 || self.bitboards[Piece::BP as usize].count() > 0 { return false; }
-        if self.bitboards[Piece::WQ as usize].count() > 0 || self.bitboards[Piece::BQ as usize].count() > 0 || self.bitboards[Piece::WR as usize].count() > 0 || self.bitboards[Piece::BR as usize].count() > 0 { return false; }
-        if self.bitboards[Piece::WB as usize].count() > 1 || self.bitboards[Piece::BB as usize].count() > 1 { return false; }
-        if self.bitboards[Piece::WN as usize].count() > 1 || self.bitboards[Piece::BN as usize].count() > 1 { return false; }
-        if self.bitboards[Piece::WN as usize].count() > 0 && self.bitboards[Piece::WB as usize].count() > 0 { return false; }
-        if self.bitboards[Piece::BN as usize].count() > 0 && self.bitboards[Piece::BB as usize].count() > 0 { return false; }
-        // Otherwise, it must be a draw:
-        true
+    pub fn reset_ply(&mut self) {
+        self.ply = 0;
     }
 
-    // Returns true if game is over
-    pub fn check_game_result(&mut self) -> bool {
-        // This move count may not be exact (?)
-        if self.fifty_move > 100 {
-            println!("1/2-1/2 (fifty move rule (claimed by {}))", PROGRAM_NAME);
-            return true;
-        }
-
-        if self.repetition_count() >= 2 {
-            println!("1/2-1/2 (3-fold repetition (claimed by {}))", PROGRAM_NAME);
-            return true;
-        }
-
-        if self.is_draw_by_material() {
-            println!("1/2-1/2 (insufficient material (claimed by {}))", PROGRAM_NAME);
-            return true;
-        }
-
-        // Check for legal move:
-        let mut found = false;
-        let move_list = self.generate_all_moves();
-        for smv in move_list.moves.into_iter() {
-            if ! self.make_move(&smv.mv) {
-                continue;
-            }
-            found = true;
-            self.undo_move();
-            break;
-        }
-        if found { return false; }
-
-        let in_check = self.square_attacked(self.king_sq[self.side], self.side^1);
-        if in_check {
-            if self.side == WHITE {
-                println!("0-1 (black mates (claimed by {}))", PROGRAM_NAME);
-            } else {
-                println!("1-0 (white mates (claimed by {}))", PROGRAM_NAME);
-            }
-        } else {
-            println!("1/2-1/2 (stalemate (claimed by {}))", PROGRAM_NAME);
+    // Take back the last move of the game, outside of the search.
+    // Returns false if there are no moves to take back.
+    pub fn take_back(&mut self) -> bool {
+        if self.history.is_empty() {
+            return false;
         }
+        // Moves made at the root leave ply at zero
+        self.ply += 1;
+        self.undo_move();
+        self.reset_ply();
         true
     }
 
-    pub fn reset_ply(&mut self) {
-        self.ply = 0;
-    }
-
     // Mirror the board, for verifying that the evaluation function is
     // symmetrical
     pub fn mirror(&mut self) -> Board {
         
         let mut board = Board::new();
+        board.variant = self.variant;
+        board.checks = [self.checks[BLACK], self.checks[WHITE]];
+        for piece in PIECE_TYPES.iter() {
+            board.pockets[piece.swap() as usize] = self.pockets[*piece as usize];
+        }
+        for sq in self.promoted {
+            board.promoted.set_bit(MIRROR64[sq as usize] as Square);
+        }
 
         if self.castle_perm & Castling::WK != 0 {
             board.castle_perm |= Castling::BK;
@@ -565,6 +701,9 @@ impl Board {
         if self.castle_perm & Castling::BQ != 0 {
             board.castle_perm |= Castling::WQ;
         }
+        for i in 0..4 {
+            board.castle_rooks[i] = MIRROR64[self.castle_rooks[i ^ 2] as usize] as Square;
+        }
 
         if self.en_pas != Position::NONE as Square {
             board.en_pas = MIRROR64[self.en_pas as usize] as Square;
@@ -575,6 +714,7 @@ impl Board {
         }
 
         board.side = self.side^1;
+        board.set_castle_mask();
         board.hash = board.get_position_hash();
         board.update_lists_and_material();
 
@@ -621,34 +761,6 @@ impl fmt::Display for Board {
     }
 }
 
-struct HashKeys {
-    // Hashing also includes EMPTY pieces
-    piece_keys: [[u64; BOARD_SQ_NUM]; NUM_PIECE_TYPES_BOTH+1],
-    side_key: u64,
-    castle_keys: [u64; 16],
-}
-
-impl HashKeys {
-    fn new() -> HashKeys {
-        let mut hasher = HashKeys {
-            piece_keys: [[0; BOARD_SQ_NUM]; NUM_PIECE_TYPES_BOTH+1],
-            side_key: 0,
-            castle_keys: [0; 16],
-        };
-
-        hasher.side_key = rand::thread_rng().gen::<u64>();
-        for i in 0..NUM_PIECE_TYPES_BOTH+1 {
-            for j in 0..64 {
-                hasher.piece_keys[i][j] = rand::thread_rng().gen::<u64>();
-            }
-        }
-        for i in 0..16 {
-            hasher.castle_keys[i] = rand::thread_rng().gen::<u64>();
-        }
-
-        hasher
-    }
-}
 
 #[allow(dead_code)]
 pub enum Position {
@@ -688,4 +800,42 @@ mod tests {
         assert_eq!(board.to_string(), s);
         assert!(board.check());
     }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in &[START_FEN,
+                     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                     "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 1",
+                     "8/8/8/8/8/3k4/3p4/3K4 b - - 0 1",
+                     "8/8/4k3/8/8/3QK3/8/8 w - - 12 40",
+                     "8/8/4k3/8/8/3QK3/8/8 b - - 0 40"] {
+            assert_eq!(Board::from_fen(fen).to_fen(), *fen);
+        }
+        let mut board = Board::from_fen(START_FEN);
+        let mv = board.parse_move("g1f3").unwrap();
+        board.make_move(&mv);
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1");
+
+        // The move number is incremented after black's move
+        let mut board = Board::from_fen("8/8/4k3/8/8/3QK3/8/8 b - - 0 40");
+        assert_eq!(board.move_number(), 40);
+        let mv = board.parse_move("e6e5").unwrap();
+        board.make_move(&mv);
+        assert_eq!(board.move_number(), 41);
+        assert_eq!(board.to_fen(), "8/8/8/4k3/8/3QK3/8/8 w - - 1 41");
+    }
+
+    #[test]
+    fn malformed_fen() {
+        assert!(Board::try_from_fen(START_FEN).is_some());
+        for fen in &["",
+                     "8/8/8/8/8/8/8 w - - 0 1",
+                     "8/8/8/9/8/8/8/8 w - - 0 1",
+                     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+                     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkz - 0 1",
+                     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1"] {
+            assert!(Board::try_from_fen(fen).is_none(), "{}", fen);
+        }
+    }
 }
@@ -1,8 +1,10 @@
+use arrayvec::ArrayVec;
+
 use crate::moves;
 use crate::board::{self,WHITE,BLACK,BOTH};
-use crate::board::{Castling,Square};
+use crate::board::Square;
 use crate::pieces::{self,Piece,PIECE_TYPES,NUM_PIECE_TYPES_BOTH,KNIGHT_MOVES,KING_MOVES};
-use crate::bitboard::{self,Bitboard,BB_RANK_4,BB_RANK_5,BB_FILE_A,BB_FILE_H};
+use crate::bitboard::{self,Bitboard,BB_RANK_1,BB_RANK_4,BB_RANK_5,BB_RANK_8,BB_FILE_A,BB_FILE_H};
 
 // Could be a method of Piece, but nice to have it here for
 // organizational purposes
@@ -22,6 +24,19 @@ lazy_static! {
     static ref MVV_LVA_SCORES: [[i32; NUM_PIECE_TYPES_BOTH]; NUM_PIECE_TYPES_BOTH] = get_mvv_lva();
 }
 
+// Pieces that a pawn can promote to, including the king in antichess
+fn promotions(b: &board::Board, side: usize) -> &'static [Piece] {
+    const PROMOTIONS: [[Piece; 5]; 2] = [
+        [Piece::WN, Piece::WB, Piece::WR, Piece::WQ, Piece::WK],
+        [Piece::BN, Piece::BB, Piece::BR, Piece::BQ, Piece::BK],
+    ];
+    if b.variant == board::Variant::Antichess {
+        &PROMOTIONS[side]
+    } else {
+        &PROMOTIONS[side][..4]
+    }
+}
+
 pub struct ScoredMove {
     pub mv: moves::Move,
     pub score: i32,
@@ -43,14 +58,23 @@ impl ScoredMove {
     }
 }
 
+// Most moves in a position.  No chess position has more than 218
+// legal moves, so 256 would do for standard chess, but Crazyhouse
+// drops come on top of the moves on the board: a full pocket can be
+// dropped on every empty square, and the first Crazyhouse position
+// in the perft tests already has 301 moves.  The list also holds
+// pseudo-legal moves, which can be more than the legal ones.
+pub const MAX_MOVES: usize = 512;
+
+// Moves are kept on the stack, as a move list is generated at every
+// node of the search
 pub struct MoveList {
-    pub moves: Vec<ScoredMove>,
+    pub moves: ArrayVec<ScoredMove, MAX_MOVES>,
 }
 
 impl MoveList {
     fn new() -> MoveList {
-        // Speed up by avoiding reallocation
-        MoveList{moves: Vec::<ScoredMove>::with_capacity(256)}
+        MoveList{moves: ArrayVec::new()}
     }
 
     fn add_quiet_move(&mut self, b: &board::Board, mv: moves::Move) {
@@ -66,7 +90,7 @@ impl MoveList {
         };
                 
         if score == 0 {
-            score = b.search_history[b.pieces[mv.from() as usize] as usize][mv.to() as usize] as i32;
+            score = b.search_history[b.moving_piece(&mv) as usize][mv.to() as usize] as i32;
         }
         
         self.moves.push(ScoredMove::new(mv, score));
@@ -75,8 +99,8 @@ impl MoveList {
     fn add_capture_move(&mut self, b: &board::Board, mv: moves::Move) {
         debug_assert!(board::square_on_board(mv.from()));
         debug_assert!(board::square_on_board(mv.to()));
-        debug_assert!(mv.capture.exists());
-        let score = MVV_LVA_SCORES[mv.capture as usize][b.pieces[mv.from() as usize] as usize] + 1_000_000;
+        debug_assert!(mv.capture().exists());
+        let score = MVV_LVA_SCORES[mv.capture() as usize][b.pieces[mv.from() as usize] as usize] + 1_000_000;
         self.moves.push(ScoredMove::new(mv, score));
     }
 
@@ -90,7 +114,7 @@ impl MoveList {
         
         if from/8 == board::RANK_7 {
             // Add a version of the move with each possible promotion
-            for promote in &[Piece::WN, Piece::WB, Piece::WR, Piece::WQ] {
+            for promote in promotions(b, WHITE) {
                 self.add_quiet_move(b, moves::Move::new(from, to, capture, *promote, moves::MoveFlag::None));
             }
         }
@@ -104,7 +128,7 @@ impl MoveList {
         
         if from/8 == board::RANK_7 {
             // Add a version of the move with each possible promotion
-            for promote in &[Piece::WN, Piece::WB, Piece::WR, Piece::WQ] {
+            for promote in promotions(b, WHITE) {
                 self.add_capture_move(b, moves::Move::new(from, to, capture, *promote, moves::MoveFlag::None));
             }
         }
@@ -117,7 +141,7 @@ impl MoveList {
         
         if from/8 == board::RANK_2 {
             // Add a version of the move with each possible promotion
-            for promote in &[Piece::BN, Piece::BB, Piece::BR, Piece::BQ] {
+            for promote in promotions(b, BLACK) {
                 self.add_quiet_move(b, moves::Move::new(from, to, capture, *promote, moves::MoveFlag::None));
             }
         }
@@ -131,7 +155,7 @@ impl MoveList {
         
         if from/8 == board::RANK_2 {
             // Add a version of the move with each possible promotion
-            for promote in &[Piece::BN, Piece::BB, Piece::BR, Piece::BQ] {
+            for promote in promotions(b, BLACK) {
                 self.add_capture_move(b, moves::Move::new(from, to, capture, *promote, moves::MoveFlag::None));
             }
         }
@@ -152,18 +176,92 @@ impl MoveList {
 
 impl board::Board {
 
+    #[inline]
     pub fn generate_all_moves(&self) -> MoveList {
-        self.generate_moves(true)
+        let mut move_list = MoveList::new();
+        self.generate_moves(true, &mut move_list);
+        move_list
     }
 
+    #[inline]
     pub fn generate_all_captures(&self) -> MoveList {
-        self.generate_moves(false)
+        let mut move_list = MoveList::new();
+        self.generate_moves(false, &mut move_list);
+        move_list
+    }
+
+    // The piece that makes a move, including drops
+    pub(super) fn moving_piece(&self, mv: &moves::Move) -> Piece {
+        if mv.is_drop() { mv.dropped() } else { self.pieces[mv.from() as usize] }
+    }
+
+    // Crazyhouse drops on all empty squares, apart from pawns on the
+    // first and last rank
+    fn add_drop_moves(&self, move_list: &mut MoveList) {
+        let empty = !self.bb_sides[BOTH].0;
+        for &piece in PIECE_TYPES.iter().filter(|p| p.color() == self.side && ! p.is_king()) {
+            if self.pocket(piece) == 0 {
+                continue;
+            }
+            let squares = if piece.is_pawn() { empty & !(BB_RANK_1 | BB_RANK_8) } else { empty };
+            for to64 in Bitboard(squares) {
+                move_list.add_quiet_move(self, moves::Move::new_drop(piece, to64));
+            }
+        }
+    }
+
+    fn add_castle_moves(&self, move_list: &mut MoveList) {
+        let side = self.side;
+        let king_from = self.king_sq[side];
+        let rank = king_from / 8;
+
+        for index in [2 * side, 2 * side + 1] {
+            if self.castle_perm & (1 << index) == 0 {
+                continue;
+            }
+            let rook_from = self.castle_rooks[index];
+            let kingside = index % 2 == 0;
+            let king_to = board::fr_to_sq(if kingside { 6 } else { 2 }, rank);
+            let rook_to = board::fr_to_sq(if kingside { 5 } else { 3 }, rank);
+
+            // All squares that the king and rook pass through must be
+            // empty, apart from the king and rook themselves
+            let between = |a: Square, b: Square| std::cmp::min(a, b)..=std::cmp::max(a, b);
+            let blocked = between(king_from, king_to).chain(between(rook_from, rook_to))
+                .any(|sq| sq != king_from && sq != rook_from && self.pieces[sq as usize] != Piece::Empty);
+            if blocked {
+                continue;
+            }
+
+            // The king may not castle out of or through check.  The
+            // destination is checked when the move is made, unless the
+            // king doesn't move, as the rook could block a check.
+            let occupancy = self.bb_sides[BOTH].0 & ! (1 << king_from);
+            if between(king_from, king_to).any(|sq| (sq == king_from || sq != king_to) && self.king_square_attacked(sq, side ^ 1, occupancy)) {
+                continue;
+            }
+
+            move_list.add_quiet_move(self, moves::Move::new(king_from, king_to, Piece::Empty, Piece::Empty, moves::MoveFlag::Castle));
+        }
     }
 
-    fn generate_moves(&self, non_captures: bool) -> MoveList {
+    // Moves are added to a list made by the caller, which can then
+    // return it without copying the whole list
+    fn generate_moves(&self, non_captures: bool, move_list: &mut MoveList) {
         debug_assert!(self.check());
 
-        let mut move_list = MoveList::new();
+        // No moves once a variant's game is over
+        if self.variant_winner().is_some() {
+            return;
+        }
+
+        // Captures are compulsory in antichess
+        if non_captures && self.variant == board::Variant::Antichess {
+            self.generate_moves(false, move_list);
+            if ! move_list.moves.is_empty() {
+                return;
+            }
+        }
 
         if self.side == pieces::WHITE {
 
@@ -206,23 +304,6 @@ impl board::Board {
                 }
             }
 
-
-            // Castling
-            if non_captures && self.castle_perm & Castling::WK != 0 {
-                if self.pieces[board::Position::F1 as usize] == Piece::Empty && self.pieces[board::Position::G1 as usize] == Piece::Empty {
-                    if (! self.square_attacked(board::Position::E1 as Square, pieces::BLACK)) && (!self.square_attacked(board::Position::F1 as Square, pieces::BLACK)) {
-                        move_list.add_quiet_move(self, moves::Move::new(board::Position::E1 as Square, board::Position::G1 as Square, Piece::Empty, Piece::Empty, moves::MoveFlag::Castle));
-                    }
-                }
-            }
-
-            if non_captures && self.castle_perm & Castling::WQ != 0 {
-                if self.pieces[board::Position::D1 as usize] == Piece::Empty && self.pieces[board::Position::C1 as usize] == Piece::Empty && self.pieces[board::Position::B1 as usize] == Piece::Empty {
-                    if (! self.square_attacked(board::Position::E1 as Square, pieces::BLACK)) && (!self.square_attacked(board::Position::D1 as Square, pieces::BLACK)) {
-                        move_list.add_quiet_move(self, moves::Move::new(board::Position::E1 as Square, board::Position::C1 as Square, Piece::Empty, Piece::Empty, moves::MoveFlag::Castle));
-                    }
-                }
-            }
         }
         else {
 
@@ -265,22 +346,15 @@ impl board::Board {
                 }
             }
 
-            // Castling
-            if non_captures && self.castle_perm & Castling::BK != 0 {
-                if self.pieces[board::Position::F8 as usize] == Piece::Empty && self.pieces[board::Position::G8 as usize] == Piece::Empty {
-                    if (! self.square_attacked(board::Position::E8 as Square, pieces::WHITE)) && (!self.square_attacked(board::Position::F8 as Square, pieces::WHITE)) {
-                        move_list.add_quiet_move(self, moves::Move::new(board::Position::E8 as Square, board::Position::G8 as Square, Piece::Empty, Piece::Empty, moves::MoveFlag::Castle));
-                    }
-                }
-            }
+        }
 
-            if non_captures && self.castle_perm & Castling::BQ != 0 {
-                if self.pieces[board::Position::D8 as usize] == Piece::Empty && self.pieces[board::Position::C8 as usize] == Piece::Empty && self.pieces[board::Position::B8 as usize] == Piece::Empty {
-                    if (! self.square_attacked(board::Position::E8 as Square, pieces::WHITE)) && (!self.square_attacked(board::Position::D8 as Square, pieces::WHITE)) {
-                        move_list.add_quiet_move(self, moves::Move::new(board::Position::E8 as Square, board::Position::C8 as Square, Piece::Empty, Piece::Empty, moves::MoveFlag::Castle));
-                    }
-                }
-            }
+        // Castling, which the king can't do in antichess
+        if non_captures && self.variant != board::Variant::Antichess {
+            self.add_castle_moves(move_list);
+        }
+
+        if non_captures && self.variant == board::Variant::Crazyhouse {
+            self.add_drop_moves(move_list);
         }
 
         // Sliders
@@ -329,7 +403,6 @@ impl board::Board {
                 }
             }
         }
-        move_list
     }
 }
 
@@ -0,0 +1,309 @@
+// Efficiently updatable neural network (NNUE) evaluation.
+//
+// The network has 768 inputs (one for each colored piece type on each
+// square), a hidden layer that is computed separately from white's
+// and black's point of view, and a single output:
+//
+//   output = crelu(acc[us]) . w_us + crelu(acc[them]) . w_them + bias
+//
+// The hidden layer sums (accumulators) only depend on which pieces
+// are on which squares, so they are updated incrementally as pieces
+// are added, cleared and moved in makemove.rs.  Because undo_move
+// applies the same operations in reverse, the accumulators are
+// restored exactly when moves are undone.
+//
+// Network file format (all values little endian):
+//   magic "CHNN", u32 version, u32 hidden size,
+//   i16 feature weights [768][hidden], i16 feature biases [hidden],
+//   i16 output weights [2*hidden], i32 output bias
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::board::*;
+
+pub const NUM_FEATURES: usize = 768;
+
+const MAGIC: &[u8; 4] = b"CHNN";
+const VERSION: u32 = 1;
+
+// Quantization of the hidden and output layers, and the scaling from
+// network output to centipawns
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+// Largest evaluation, well below the mate scores
+const MAX_EVAL: i64 = 20000;
+
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Reads little endian values from the network file
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("network file is truncated"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn i16_vec(&mut self, n: usize) -> io::Result<Vec<i16>> {
+        let b = self.take(2 * n)?;
+        Ok(b.chunks(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect())
+    }
+}
+
+impl Network {
+    pub fn load(path: &str) -> io::Result<Network> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Network> {
+        let mut reader = Reader{bytes: bytes};
+
+        if reader.take(4)? != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+        if reader.u32()? != VERSION {
+            return Err(invalid("unsupported network version"));
+        }
+        let hidden = reader.u32()? as usize;
+        if hidden == 0 || hidden > 4096 {
+            return Err(invalid("invalid hidden layer size"));
+        }
+
+        let net = Network {
+            hidden: hidden,
+            feature_weights: reader.i16_vec(NUM_FEATURES * hidden)?,
+            feature_bias: reader.i16_vec(hidden)?,
+            output_weights: reader.i16_vec(2 * hidden)?,
+            output_bias: reader.i32()?,
+        };
+
+        if ! reader.bytes.is_empty() {
+            return Err(invalid("unexpected data at end of network file"));
+        }
+        Ok(net)
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+}
+
+// Input index of a piece on a square, as seen from the given side.
+// Black's view flips the board and swaps the colors, so that the
+// network always sees the position from "our" side.
+fn feature_index(piece: Piece, sq: Square, perspective: usize) -> usize {
+    let (color, sq) = if perspective == WHITE {
+        (piece.color(), sq)
+    } else {
+        (piece.color() ^ 1, sq ^ 56)
+    };
+    (color * 6 + piece as usize % 6) * 64 + sq as usize
+}
+
+#[derive(Clone)]
+pub struct Nnue {
+    net: Arc<Network>,
+    // Hidden layer sums from white's and black's point of view
+    acc: [Vec<i32>; 2],
+}
+
+impl Nnue {
+    fn new(net: Arc<Network>) -> Nnue {
+        let bias: Vec<i32> = net.feature_bias.iter().map(|&b| b as i32).collect();
+        Nnue {
+            net: net,
+            acc: [bias.clone(), bias],
+        }
+    }
+
+    // Recompute the accumulators from scratch
+    fn refresh(&mut self, pieces: &[Piece; BOARD_SQ_NUM]) {
+        for side in 0..2 {
+            for (a, &b) in self.acc[side].iter_mut().zip(self.net.feature_bias.iter()) {
+                *a = b as i32;
+            }
+        }
+        for (sq, &piece) in pieces.iter().enumerate() {
+            if piece.exists() {
+                self.add(piece, sq as Square);
+            }
+        }
+    }
+
+    pub fn add(&mut self, piece: Piece, sq: Square) {
+        let hidden = self.net.hidden;
+        for side in 0..2 {
+            let i = feature_index(piece, sq, side) * hidden;
+            let weights = &self.net.feature_weights[i..i+hidden];
+            for (a, &w) in self.acc[side].iter_mut().zip(weights) {
+                *a += w as i32;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, piece: Piece, sq: Square) {
+        let hidden = self.net.hidden;
+        for side in 0..2 {
+            let i = feature_index(piece, sq, side) * hidden;
+            let weights = &self.net.feature_weights[i..i+hidden];
+            for (a, &w) in self.acc[side].iter_mut().zip(weights) {
+                *a -= w as i32;
+            }
+        }
+    }
+
+    // Evaluate position for side to move
+    pub fn evaluate(&self, side: usize) -> i32 {
+        let hidden = self.net.hidden;
+        let (us, them) = self.net.output_weights.split_at(hidden);
+
+        // Summed in 64 bits, as a large network can overflow 32
+        let mut sum = self.net.output_bias as i64;
+        for (&a, &w) in self.acc[side].iter().zip(us) {
+            sum += (a.clamp(0, QA) * w as i32) as i64;
+        }
+        for (&a, &w) in self.acc[side^1].iter().zip(them) {
+            sum += (a.clamp(0, QA) * w as i32) as i64;
+        }
+
+        (sum * SCALE as i64 / (QA * QB) as i64).clamp(-MAX_EVAL, MAX_EVAL) as i32
+    }
+}
+
+impl Board {
+    // Use the given network for evaluation, or the handcrafted
+    // evaluation if None
+    pub fn set_network(&mut self, net: Option<Arc<Network>>) {
+        self.nnue = net.map(|net| {
+            let mut nnue = Nnue::new(net);
+            nnue.refresh(&self.pieces);
+            nnue
+        });
+    }
+
+    pub fn network(&self) -> Option<Arc<Network>> {
+        self.nnue.as_ref().map(|nnue| nnue.net.clone())
+    }
+
+    // Verify the incrementally updated accumulators
+    pub(super) fn check_nnue(&self) -> bool {
+        if let Some(nnue) = &self.nnue {
+            let mut fresh = nnue.clone();
+            fresh.refresh(&self.pieces);
+            assert!(fresh.acc == nnue.acc);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A randomly initialized network with 8 hidden neurons.  It plays
+    // terribly, but is enough to test the evaluation plumbing.
+    const TEST_NET: &'static str = "nets/test.nnue";
+
+    fn test_board(fen: &str) -> Board {
+        let mut board = Board::from_fen(fen);
+        board.set_network(Some(Arc::new(Network::load(TEST_NET).unwrap())));
+        board
+    }
+
+    #[test]
+    fn load_network() {
+        let net = Network::load(TEST_NET).unwrap();
+        assert_eq!(net.hidden_size(), 8);
+        assert!(Network::from_bytes(b"CHNN").is_err());
+        assert!(Network::from_bytes(b"XXXX\x01\x00\x00\x00\x08\x00\x00\x00").is_err());
+    }
+
+    // The largest network with the largest weights
+    #[test]
+    fn no_overflow() {
+        let hidden = 4096;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(hidden as u32).to_le_bytes());
+        bytes.resize(bytes.len() + 2 * NUM_FEATURES * hidden, 0);
+        for _ in 0..hidden {
+            bytes.extend_from_slice(&i16::MAX.to_le_bytes());
+        }
+        for _ in 0..2 * hidden {
+            bytes.extend_from_slice(&i16::MAX.to_le_bytes());
+        }
+        bytes.extend_from_slice(&i32::MAX.to_le_bytes());
+
+        let nnue = Nnue::new(Arc::new(Network::from_bytes(&bytes).unwrap()));
+        assert_eq!(nnue.evaluate(WHITE), MAX_EVAL as i32);
+    }
+
+    // The perspective inputs make the network symmetric by construction
+    #[test]
+    fn mirror_symmetry() {
+        let mut board = test_board("2k1r2r/Bpq3pp/3b4/3Bp3/8/7b/PPP1QP2/R3R1K1 w - - 0 1");
+        let score = board.evaluate();
+        assert_ne!(score, board.evaluate_with(&DEFAULT_EVAL_PARAMS, &mut NoTrace));
+
+        let mut mirrored = board.mirror();
+        mirrored.set_network(board.network());
+        assert_eq!(score, mirrored.evaluate());
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = test_board(fen);
+        let score = board.evaluate();
+
+        // Make and undo every move, including castling, captures and
+        // promotions (check_nnue is also run by the debug assertions)
+        let move_list = board.generate_all_moves();
+        for smv in move_list.moves.iter() {
+            if board.make_move(&smv.mv) {
+                assert!(board.check_nnue());
+                board.undo_move();
+            }
+        }
+        assert_eq!(score, board.evaluate());
+
+        // The same when the position is restored instead
+        for smv in move_list.moves.iter() {
+            let state = board.snapshot();
+            if board.make_move(&smv.mv) {
+                board.restore_snapshot(&state);
+                assert!(board.check_nnue());
+            }
+        }
+        assert_eq!(score, board.evaluate());
+    }
+}
@@ -0,0 +1,117 @@
+// Reporting search progress.
+//
+// The search itself doesn't print anything.  Instead it reports each
+// completed iteration and the final result to a SearchObserver, which
+// formats them for the protocol in use.
+
+use std::sync::{Arc,Mutex};
+use std::time::Duration;
+
+use crate::moves::Move;
+use crate::version::PROGRAM_NAME;
+use super::adjudicate::{Adjudicator,Decision};
+use super::castling::MoveFormat;
+
+// A completed iteration of iterative deepening
+pub struct Iteration<'a> {
+    pub depth: u32,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: &'a [Move],
+}
+
+// Result of a search, from the last completed iteration
+#[derive(Clone)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+pub trait SearchObserver {
+    fn iteration(&mut self, _iteration: &Iteration) {}
+
+    fn finished(&mut self, _result: &SearchResult) {}
+}
+
+fn pv_string(pv: &[Move]) -> String {
+    pv.iter().map(|mv| mv.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// Reports nothing, for library use, tests and benchmarks
+pub struct SilentObserver;
+
+impl SearchObserver for SilentObserver {}
+
+pub struct UciObserver {
+    // Resign and draw decisions are reported as info strings, for
+    // GUIs that adjudicate
+    pub adjudicator: Arc<Mutex<Adjudicator>>,
+    pub move_number: u32,
+    // Castling is written as the king capturing its rook with
+    // UCI_Chess960
+    pub move_format: MoveFormat,
+}
+
+impl SearchObserver for UciObserver {
+    fn iteration(&mut self, it: &Iteration) {
+        println!("info score cp {} depth {} nodes {} time {} pv {}",
+                 it.score, it.depth, it.nodes, it.time.as_millis(), self.move_format.line(it.pv));
+    }
+
+    fn finished(&mut self, result: &SearchResult) {
+        match result.best_move {
+            Some(mv) => {
+                match self.adjudicator.lock().unwrap().update(result.score, self.move_number) {
+                    Decision::Resign => println!("info string resign"),
+                    Decision::OfferDraw => println!("info string offer draw"),
+                    Decision::Play => (),
+                }
+                println!("bestmove {}", self.move_format.format(&mv));
+            }
+            // Every go needs a reply, even with no legal moves
+            None => println!("bestmove 0000"),
+        }
+    }
+}
+
+// Only reports thinking output.  The xboard loop sends the move, as
+// it may resign instead.
+pub struct XboardObserver {
+    // Thinking output, turned on and off with post and nopost
+    pub show_thinking: bool,
+    pub move_format: MoveFormat,
+}
+
+impl SearchObserver for XboardObserver {
+    fn iteration(&mut self, it: &Iteration) {
+        if self.show_thinking {
+            // Time is in centiseconds
+            println!("{} {} {} {} {}",
+                     it.depth, it.score, it.time.as_millis() / 10, it.nodes, self.move_format.line(it.pv));
+        }
+    }
+}
+
+pub struct ConsoleObserver {
+    pub show_thinking: bool,
+}
+
+impl SearchObserver for ConsoleObserver {
+    fn iteration(&mut self, it: &Iteration) {
+        if self.show_thinking {
+            println!("score {} depth {} nodes {} time {} pv {}",
+                     it.score, it.depth, it.nodes, it.time.as_millis(), pv_string(it.pv));
+        }
+    }
+
+    fn finished(&mut self, result: &SearchResult) {
+        if let Some(mv) = result.best_move {
+            println!("{} makes move: {}", PROGRAM_NAME, mv);
+        }
+    }
+}
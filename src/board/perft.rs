@@ -52,7 +52,47 @@ mod tests {
         assert_eq!(count, 2039);
     }
 
-    // Full test suite:
+    // Chess960, with castling from unusual squares, and the king
+    // castling to its own square or to a rook's square
+    #[test]
+    fn perft_960() {
+        perft_test_line("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189", 3);
+        perft_test_line("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9 ;D1 21 ;D2 807 ;D3 18002", 3);
+        perft_test_line("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9 ;D1 20 ;D2 479 ;D3 10471", 3);
+        perft_test_line("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9 ;D1 22 ;D2 593 ;D3 13440", 3);
+    }
+
+    // Variants, with results from shakmaty: its test suites, and for
+    // King of the Hill, which has none, its perft function (shakmaty
+    // 0.30 with the variant feature)
+    const VARIANT_PERFT: [(Variant, &'static str); 17] = [
+        (Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 ;D1 48 ;D2 2039 ;D3 97848"),
+        (Variant::ThreeCheck, "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 ;D1 26 ;D2 562 ;D3 13410"),
+        (Variant::KingOfTheHill, "r1bq1bnr/pppp1ppp/2n1k3/4p3/4P3/2N1K3/PPPP1PPP/R1BQ1BNR w - - 0 1 ;D1 34 ;D2 1088 ;D3 33743 ;D4 1027425 ;D5 31110876"),
+        (Variant::KingOfTheHill, "8/8/8/2k5/8/4K3/8/8 b - - 0 1 ;D1 7 ;D2 46 ;D3 272 ;D4 1916 ;D5 12341"),
+        (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902 ;D4 197326"),
+        (Variant::Atomic, "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - ;D1 40 ;D2 1238 ;D3 45237 ;D4 1434825"),
+        (Variant::Atomic, "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - ;D1 28 ;D2 833 ;D3 23353 ;D4 714499"),
+        (Variant::Atomic, "8/8/8/8/8/8/2k5/rR4KR w KQ - ;D1 18 ;D2 180 ;D3 4364 ;D4 61401 ;D5 1603055"),
+        (Variant::Atomic, "r3k1rR/5K2/8/8/8/8/8/8 b kq - ;D1 25 ;D2 282 ;D3 6753 ;D4 98729 ;D5 2587730"),
+        (Variant::Atomic, "Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - ;D1 21 ;D2 465 ;D3 10631 ;D4 241478 ;D5 5800275"),
+        (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - ;D1 301 ;D2 75353"),
+        (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[Qn] w - - ;D1 67 ;D2 3083 ;D3 88634 ;D4 932554"),
+        (Variant::Crazyhouse, "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - ;D1 42 ;D2 1347 ;D3 58057 ;D4 2083382"),
+        (Variant::Crazyhouse, "4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1 ;D1 20 ;D2 360 ;D3 5445 ;D4 132758"),
+        (Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - ;D1 20 ;D2 400 ;D3 8067 ;D4 153299"),
+        (Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - ;D1 2 ;D2 4 ;D3 4 ;D4 3 ;D5 1 ;D6 0"),
+        (Variant::Antichess, "8/2p5/8/8/8/8/P7/8 w - - ;D1 2 ;D2 4 ;D3 4 ;D4 4 ;D5 4 ;D6 4 ;D7 4 ;D8 4 ;D9 12 ;D10 36 ;D11 312 ;D12 2557 ;D13 30873"),
+    ];
+
+    #[test]
+    fn perft_variants() {
+        for &(variant, line) in VARIANT_PERFT.iter() {
+            perft_test_variant_line(variant, line, 3);
+        }
+    }
+
+    // Full test suites:
 
     #[test]
     #[ignore]
@@ -66,12 +106,38 @@ mod tests {
         }
     }
 
+    #[test]
+    #[ignore]
+    fn perft_test_variants() {
+        for &(variant, line) in VARIANT_PERFT.iter() {
+            perft_test_variant_line(variant, line, 5);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn perft_test_960() {
+        // The suite goes to depth 6, which takes too long for all 960
+        // positions
+        const MAX_DEPTH: u32 = 4;
+        let f = File::open("perft960.txt").expect("error opening perft960.txt");
+        let f = BufReader::new(f);
+        for line in f.lines() {
+            perft_test_line(line.unwrap().as_str(), MAX_DEPTH);
+        }
+    }
+
     // Run perft test on an individual line from the test suite
     fn perft_test_line(line: &str, max_depth: u32) {
-        println!("Testing: {}", line);
+        perft_test_variant_line(Variant::Standard, line, max_depth);
+    }
+
+    fn perft_test_variant_line(variant: Variant, line: &str, max_depth: u32) {
+        println!("Testing: {} {}", variant.name(), line);
         let mut items = line.split(';');
         let fen = items.next().unwrap();
         let mut board = Board::from_fen(fen);
+        board.set_variant(variant);
 
         for depth_entry in items {
             let vals: Vec<&str> = depth_entry.split_whitespace().collect();
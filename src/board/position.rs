@@ -0,0 +1,173 @@
+// Snapshots of the position, for taking back moves in the search.
+//
+// make_move updates the board incrementally and records an Undo, which
+// undo_move uses to reverse each update, and which the repetition
+// check reads.  Making a move is unchanged here; instead of undoing
+// it, a snapshot taken before the move is copied back, and the Undo
+// is dropped.  The snapshot is a few hundred bytes, so copying it back
+// is cheaper than reversing the move piece by piece.  The search uses
+// snapshots, while undo_move remains for taking back moves outside the
+// search.
+
+use crate::board::*;
+
+// The part of the board that changes when a move is made
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct PositionState {
+    pieces: [Piece; BOARD_SQ_NUM],
+    bitboards: [Bitboard; NUM_PIECE_TYPES_BOTH],
+    bb_sides: [Bitboard; 3],
+
+    num_big_piece: [i32; 2],
+    num_major_piece: [i32; 2],
+    num_minor_piece: [i32; 2],
+    material: [i32; 2],
+    king_sq: [Square; 2],
+
+    side: usize,
+    en_pas: Square,
+    fifty_move: u32,
+    castle_perm: u8,
+    hash: u64,
+
+    checks: [u8; 2],
+    pockets: [u8; NUM_PIECE_TYPES_BOTH],
+    promoted: Bitboard,
+}
+
+impl PositionState {
+    pub fn piece(&self, sq: Square) -> Piece {
+        self.pieces[sq as usize]
+    }
+
+    pub fn side(&self) -> usize {
+        self.side
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        if self.en_pas == Position::NONE as Square { None } else { Some(self.en_pas) }
+    }
+
+    // Castling rights, as the bits of Castling
+    pub fn castle_perm(&self) -> u8 {
+        self.castle_perm
+    }
+
+    // Half moves since the last capture or pawn move
+    pub fn fifty_move(&self) -> u32 {
+        self.fifty_move
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Board {
+    // Copy of the current position, to be restored after making a move
+    pub fn snapshot(&self) -> PositionState {
+        PositionState{
+            pieces: self.pieces,
+            bitboards: self.bitboards,
+            bb_sides: self.bb_sides,
+
+            num_big_piece: self.num_big_piece,
+            num_major_piece: self.num_major_piece,
+            num_minor_piece: self.num_minor_piece,
+            material: self.material,
+            king_sq: self.king_sq,
+
+            side: self.side,
+            en_pas: self.en_pas,
+            fifty_move: self.fifty_move,
+            castle_perm: self.castle_perm,
+            hash: self.hash,
+
+            checks: self.checks,
+            pockets: self.pockets,
+            promoted: self.promoted,
+        }
+    }
+
+    // Take back the last move by restoring the snapshot taken before
+    // it was made, in place of undo_move
+    pub fn restore_snapshot(&mut self, state: &PositionState) {
+        debug_assert!(self.check());
+
+        self.hist_ply -= 1;
+        self.ply -= 1;
+        self.history.pop();
+
+        // The network is updated for the squares that differ, which
+        // are only the few that the move changed
+        if let Some(nnue) = self.nnue.as_mut() {
+            for sq in 0..BOARD_SQ_NUM {
+                let (now, before) = (self.pieces[sq], state.pieces[sq]);
+                if now != before {
+                    if now.exists() {
+                        nnue.remove(now, sq as Square);
+                    }
+                    if before.exists() {
+                        nnue.add(before, sq as Square);
+                    }
+                }
+            }
+        }
+
+        self.pieces = state.pieces;
+        self.bitboards = state.bitboards;
+        self.bb_sides = state.bb_sides;
+
+        self.num_big_piece = state.num_big_piece;
+        self.num_major_piece = state.num_major_piece;
+        self.num_minor_piece = state.num_minor_piece;
+        self.material = state.material;
+        self.king_sq = state.king_sq;
+
+        self.side = state.side;
+        self.en_pas = state.en_pas;
+        self.fifty_move = state.fifty_move;
+        self.castle_perm = state.castle_perm;
+        self.hash = state.hash;
+
+        self.checks = state.checks;
+        self.pockets = state.pockets;
+        self.promoted = state.promoted;
+
+        debug_assert!(self.check());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::*;
+
+    // Restoring the snapshot must give the same board as undoing the
+    // move, for every move in a few positions with special moves
+    #[test]
+    fn restore_matches_undo() {
+        for &(variant, fen) in &[
+            (Variant::Standard, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+            (Variant::Standard, "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1"),
+            (Variant::Atomic, "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1"),
+            (Variant::Crazyhouse, "4k3/1Q~6/8/8/4b3/8/Kpp5/8/Nr b - - 0 1"),
+            (Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1"),
+        ] {
+            let mut board = Board::from_fen(fen);
+            board.set_variant(variant);
+            let fen = board.to_fen();
+            let hash = board.hash;
+            for smv in board.generate_all_moves().moves.iter() {
+                let state = board.snapshot();
+                if board.make_move(&smv.mv) {
+                    board.restore_snapshot(&state);
+                }
+                assert_eq!(board.to_fen(), fen);
+                assert_eq!(board.hash, hash);
+                assert!(board.history.is_empty());
+                assert!(board.check());
+            }
+        }
+    }
+}
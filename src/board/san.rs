@@ -0,0 +1,143 @@
+// Standard algebraic notation (SAN), as used in PGN files.
+
+use crate::board::*;
+use crate::moves::Move;
+
+impl Board {
+    // Returns the legal move matching a SAN string such as "Nbd7",
+    // "exd5", "e8=Q+", "O-O" or the Crazyhouse drop "N@f3".  Check and annotation suffixes are
+    // ignored.
+    pub fn parse_san(&mut self, san: &str) -> Option<Move> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+
+        // Castling, identified by the file the king moves to
+        let castle_file = match san {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+
+        let mut chars: Vec<char> = san.chars().collect();
+        let mut piece = 'P';
+        let mut promote = None;
+        let mut dest = None;
+        let mut from_file = None;
+        let mut from_rank = None;
+        let drop = chars.contains(&'@');
+
+        if castle_file.is_none() {
+            if let Some(&c @ ('N' | 'B' | 'R' | 'Q' | 'K' | 'P')) = chars.first() {
+                piece = c;
+                chars.remove(0);
+            }
+            if let Some(&c @ ('N' | 'B' | 'R' | 'Q' | 'K')) = chars.last() {
+                promote = Some(c);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+            chars.retain(|&c| c != 'x' && c != '@');
+            if chars.len() < 2 {
+                return None;
+            }
+            let (file, rank) = (chars[chars.len() - 2], chars[chars.len() - 1]);
+            if ! ('a'..='h').contains(&file) || ! ('1'..='8').contains(&rank) {
+                return None;
+            }
+            dest = Some(fr_to_sq(file as u8 - b'a', rank as u8 - b'1'));
+
+            // Disambiguation
+            for &c in &chars[..chars.len() - 2] {
+                match c {
+                    'a'..='h' => from_file = Some(c as u8 - b'a'),
+                    '1'..='8' => from_rank = Some(c as u8 - b'1'),
+                    _ => return None,
+                }
+            }
+            // Pawn captures always give the file
+            if piece == 'P' && from_file.is_none() {
+                from_file = Some(file as u8 - b'a');
+            }
+        }
+
+        let mut found = None;
+        for smv in self.generate_all_moves().moves.into_iter() {
+            let mv = smv.mv;
+            let matches = match castle_file {
+                Some(file) => mv.is_castle() && mv.to() % 8 == file,
+                None => {
+                    let moving = self.moving_piece(&mv).to_string().to_ascii_uppercase();
+                    let promoted = if mv.is_promotion() {
+                        mv.promote().to_string().to_ascii_uppercase().chars().next()
+                    } else {
+                        None
+                    };
+                    Some(mv.to()) == dest && ! mv.is_castle() && mv.is_drop() == drop
+                        && moving.starts_with(piece)
+                        && from_file.is_none_or(|file| mv.from() % 8 == file)
+                        && from_rank.is_none_or(|rank| mv.from() / 8 == rank)
+                        && promoted == promote
+                }
+            };
+            if ! matches || ! self.make_move(&mv) {
+                continue;
+            }
+            self.undo_move();
+            if found.is_some() {
+                // Ambiguous
+                return None;
+            }
+            found = Some(mv);
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, san: &str) -> Option<String> {
+        Board::from_fen(fen).parse_san(san).map(|mv| mv.to_string())
+    }
+
+    #[test]
+    fn parse_san() {
+        assert_eq!(san(START_FEN, "e4"), Some("e2e4".to_string()));
+        assert_eq!(san(START_FEN, "Nf3"), Some("g1f3".to_string()));
+        assert_eq!(san(START_FEN, "e5"), None);
+        assert_eq!(san(START_FEN, "Ne2"), None);
+
+        // Disambiguation by file and rank
+        let fen = "4k3/8/8/8/8/8/4K3/R6R w - - 0 1";
+        assert_eq!(san(fen, "Rd1"), None);
+        assert_eq!(san(fen, "Rad1"), Some("a1d1".to_string()));
+        let fen = "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(san(fen, "O-O"), Some("e1g1".to_string()));
+        assert_eq!(san(fen, "O-O-O+"), Some("e1c1".to_string()));
+        let fen = "4k3/8/8/8/R7/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(fen, "R1a2"), Some("a1a2".to_string()));
+
+        // Captures, en passant and promotion
+        let fen = "1r2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(san(fen, "exd6"), Some("e5d6".to_string()));
+        assert_eq!(san(fen, "a8=Q+"), Some("a7a8q".to_string()));
+        assert_eq!(san(fen, "axb8N"), Some("a7b8n".to_string()));
+        assert_eq!(san(fen, "a8"), None);
+        let fen = "4k3/8/8/8/4p3/3P4/8/4K3 w - - 0 1";
+        assert_eq!(san(fen, "e4"), None);
+        assert_eq!(san(fen, "dxe4"), Some("d3e4".to_string()));
+
+        // Pinned knight
+        let fen = "4k3/4r3/8/8/8/2N1N3/8/4K3 w - - 0 1";
+        assert_eq!(san(fen, "Nd5"), Some("c3d5".to_string()));
+
+        // Crazyhouse drops
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1");
+        board.set_variant(Variant::Crazyhouse);
+        assert_eq!(board.parse_san("N@f3").map(|mv| mv.to_string()), Some("N@f3".to_string()));
+        assert_eq!(board.parse_san("@e4").map(|mv| mv.to_string()), Some("P@e4".to_string()));
+        assert!(board.parse_san("e4").is_none());
+    }
+}
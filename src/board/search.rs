@@ -1,9 +1,13 @@
-use std::time::{Duration, Instant};
-use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool,AtomicU32,AtomicU64,Ordering};
 
 use crate::board::*;
 use crate::moves;
 use self::movegen::MoveList;
+use self::timeman::TimeManager;
+use self::clock::{Clock,SystemClock};
+use self::observer::{SearchObserver,SilentObserver,Iteration,SearchResult};
 
 
 const MATE: i32 = 29000;
@@ -11,19 +15,54 @@ const MATE: i32 = 29000;
 // Avoid overflow when negating
 const I32_SAFE_MIN: i32 = std::i32::MIN + 1;
 
-#[allow(dead_code)]
-#[derive(PartialEq)]
-pub enum GameMode {
-    Uci,
-    Xboard,
-    Console,
-    None,
+// Progress of a running search, which can be read from other threads
+pub struct SearchProgress {
+    nodes: AtomicU64,
+    depth: AtomicU32,
+    // Root moves in the current iteration
+    moves_searched: AtomicU32,
+    moves_total: AtomicU32,
+    current_move: Mutex<Option<moves::Move>>,
 }
 
-pub struct SearchInfo<'a> {
-    start_time: Instant,
-    have_time_limit: bool,
-    time_limit: Duration,
+impl SearchProgress {
+    fn new() -> SearchProgress {
+        SearchProgress {
+            nodes: AtomicU64::new(0),
+            depth: AtomicU32::new(0),
+            moves_searched: AtomicU32::new(0),
+            moves_total: AtomicU32::new(0),
+            current_move: Mutex::new(None),
+        }
+    }
+
+    // Nodes are only updated at each checkup
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    // Depth of the iteration being searched
+    pub fn depth(&self) -> u32 {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    pub fn moves_left(&self) -> u32 {
+        self.moves_total().saturating_sub(self.moves_searched.load(Ordering::Relaxed))
+    }
+
+    pub fn moves_total(&self) -> u32 {
+        self.moves_total.load(Ordering::Relaxed)
+    }
+
+    // Root move being searched
+    pub fn current_move(&self) -> Option<moves::Move> {
+        *self.current_move.lock().unwrap()
+    }
+}
+
+pub struct SearchInfo {
+    clock: Box<dyn Clock + Send>,
+    pub time: TimeManager,
     
     depth: u32,
     // depth_set: bool,
@@ -34,24 +73,22 @@ pub struct SearchInfo<'a> {
     // Count of all positioned visited
     nodes: u64,
 
-    pub quit: bool,
     stopped: bool,
+    // Set from another thread to stop the search
+    stop_flag: Arc<AtomicBool>,
+    progress: Arc<SearchProgress>,
 
     fail_high: u32,
     fail_high_first: u32,
 
-    message_channel: Option<&'a Receiver<String>>,
-
-    game_mode: GameMode,
-    show_thinking: bool,
+    observer: Box<dyn SearchObserver + Send>,
 }
 
-impl<'a> SearchInfo<'a> {
-    pub fn new(depth: u32, game_mode: GameMode) -> SearchInfo<'a> {
+impl SearchInfo {
+    pub fn new(depth: u32) -> SearchInfo {
         SearchInfo{
-            start_time: Instant::now(),
-            time_limit: Duration::new(0,0),
-            have_time_limit: false,
+            clock: Box::new(SystemClock::new()),
+            time: TimeManager::new(),
             
             depth: depth,
             // depth_set: true,
@@ -61,68 +98,37 @@ impl<'a> SearchInfo<'a> {
 
             nodes: 0,
             
-            quit: false,
             stopped: false,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            progress: Arc::new(SearchProgress::new()),
 
             fail_high: 0,
             fail_high_first: 0,
 
-            message_channel: None,
-
-            game_mode: game_mode,
-            show_thinking: true,
+            observer: Box::new(SilentObserver),
         }
     }
 
-    // Set the time limit and start counting
-    fn set_time_limit(&mut self, duration: Duration) {
-        self.start_time = Instant::now();
-        self.time_limit = duration;
-        self.have_time_limit = true;
-    }
-
     // Set search time based on current clock conditions, and start counting
     //
     // If time_left and move_time are both None, then unset the clock.
     // move_time should be mutually exclusive with time_left and inc
     pub fn set_search_time(&mut self, time_left: Option<u64>, move_time: Option<u64>, moves_to_go: u32, increment: Option<u64>) {
-        const BUFFER: Duration = Duration::from_millis(50);
-        // Lower limit to make sure we have enough time to at least find a move
-        const MIN_TIME: Duration = Duration::from_millis(50);
-
-        let mut time_avail: Duration;
-
-        if let Some(mt) = move_time {
-            time_avail = Duration::from_millis(mt);
-        } else if let Some(tl) = time_left {
-            if let Some(inc) = increment {
-                // This is different from VICE.  VICE adds the
-                // increment to the current move, but don't believe
-                // that is correct.  With a large increment relative
-                // to time left, the time allocated to thinking could
-                // exceed the time left.
-                time_avail = Duration::from_millis(tl + (moves_to_go as u64 - 1) * inc);
-            } else {
-                time_avail = Duration::from_millis(tl);
-            }
-            time_avail /= moves_to_go;
-        } else {
-            // Don't have any time settings, so just make sure the
-            // time limit is not set
-            self.unset_time_limit();
-            return;
-        }
-        if time_avail > BUFFER {
-            time_avail -= BUFFER;
-        }
-        time_avail = std::cmp::max(MIN_TIME, time_avail);
+        self.clock.start();
+        self.time.set_limits(time_left, move_time, moves_to_go, increment);
+    }
+
+    // Replace the clock used to time searches
+    pub fn set_clock(&mut self, clock: Box<dyn Clock + Send>) {
+        self.clock = clock;
+    }
 
-        self.set_time_limit(time_avail);
-        println!("Search time set: {:?}", time_avail);
+    fn elapsed(&self) -> Duration {
+        self.clock.elapsed(self.nodes)
     }
 
     pub fn unset_time_limit(&mut self) {
-        self.have_time_limit = false;
+        self.time.unset_limits();
     }
 
     pub fn set_depth(&mut self, depth: u32) {
@@ -130,29 +136,19 @@ impl<'a> SearchInfo<'a> {
         // self.depth_set = true;
     }
 
-    pub fn set_show_thinking(&mut self, show: bool) {
-        self.show_thinking = show;
+    // Set where search progress is reported
+    pub fn set_observer(&mut self, observer: Box<dyn SearchObserver + Send>) {
+        self.observer = observer;
     }
 
     pub fn checkup(&mut self) {
-        if self.have_time_limit && self.start_time.elapsed() > self.time_limit {
+        if self.time.hard_stop(self.elapsed()) {
             self.stopped = true;
         }
-        if let Some(rx) = self.message_channel {
-            if let Ok(m) = rx.try_recv() {
-                // println!("Message received: {}", m);
-                if m.starts_with("quit") {
-                    self.quit = true;
-                    self.stopped = true;
-                } else if m.starts_with("stop") {
-                    // UCI
-                    self.stopped = true;
-                } else if m.starts_with("?") {
-                    // Used by xboard
-                    self.stopped = true;
-                }
-            }
+        if self.stop_flag.load(Ordering::Relaxed) {
+            self.stopped = true;
         }
+        self.progress.nodes.store(self.nodes, Ordering::Relaxed);
     }
 
     pub fn maybe_checkup(&mut self) {
@@ -161,8 +157,18 @@ impl<'a> SearchInfo<'a> {
         }
     }
 
-    pub fn set_receiver(&mut self, rx: &'a Receiver<String>) {
-        self.message_channel = Some(rx);
+    // Flag that stops the search when set, which can be shared with
+    // other threads
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop_flag.clone()
+    }
+
+    pub fn progress(&self) -> Arc<SearchProgress> {
+        self.progress.clone()
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
     }
 }
 
@@ -182,70 +188,62 @@ fn pick_next_move(move_num: usize, move_list: &mut MoveList) {
 }
 
 impl Board {
-    pub fn search(&mut self, info: &mut SearchInfo) -> Option<moves::Move> {
-        let mut best_move: Option<moves::Move> = None;
-        let mut best_score;
+    pub fn search(&mut self, info: &mut SearchInfo) -> SearchResult {
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            time: Duration::new(0, 0),
+            pv: Vec::new(),
+        };
 
         self.clear_for_search(info);
 
         // Iterative deepening
         for current_depth in 1..=info.depth {
-            best_score = self.alpha_beta(I32_SAFE_MIN, std::i32::MAX, current_depth, info, true);
+            info.progress.depth.store(current_depth, Ordering::Relaxed);
+            info.progress.moves_searched.store(0, Ordering::Relaxed);
+            let best_score = self.alpha_beta(I32_SAFE_MIN, i32::MAX, current_depth, info, true);
 
             if info.stopped {
                 break;
             }
             
             self.get_pv_line(current_depth);
-            best_move = Some(self.pv_array[0]);
-
-            match info.game_mode {
-                GameMode::Uci => {
-                    print!("info score cp {} depth {} nodes {} time {} ",
-                           best_score, current_depth, info.nodes, info.start_time.elapsed().as_millis());
-                }
-                GameMode::Xboard if info.show_thinking => {
-                    print!("{} {} {} {} ",
-                            current_depth, best_score, info.start_time.elapsed().as_millis() / 10, info.nodes);
-                }
-                GameMode::Console if info.show_thinking => {
-                    print!("score {} depth {} nodes {} time {} ",
-                           best_score, current_depth, info.nodes, info.start_time.elapsed().as_millis());
-                }
-                _ => (),
-            }
-            if info.game_mode == GameMode::Uci || info.show_thinking {
-                if info.game_mode != GameMode::Xboard {
-                    print!("pv");
-                }
-                for mv in &self.pv_array {
-                    print!(" {}", mv);
-                }
-                println!("");
+            // The game is over, so there is no move to play
+            if self.pv_array.is_empty() {
+                result.score = best_score;
+                result.depth = current_depth;
+                break;
             }
+            info.time.update(self.pv_array[0], best_score);
+
+            result.best_move = Some(self.pv_array[0]);
+            result.score = best_score;
+            result.depth = current_depth;
+            result.pv = self.pv_array.clone();
+
+            info.observer.iteration(&Iteration {
+                depth: current_depth,
+                score: best_score,
+                nodes: info.nodes,
+                time: info.elapsed(),
+                pv: &self.pv_array,
+            });
 
             // println!("Ordering: {:.2}", info.fail_high_first as f32 /info.fail_high as f32);
-        }
 
-        match info.game_mode {
-            GameMode::Uci => {
-                if let Some(mv) = best_move {
-                    println!("bestmove {}", mv);
-                }
-            }
-            GameMode::Xboard => {
-                if let Some(mv) = best_move {
-                    println!("move {}", mv);
-                }
-            }
-            GameMode::Console => {
-                if let Some(mv) = best_move {
-                    println!("{} makes move: {}", PROGRAM_NAME, mv);
-                }
+            // Not enough time left to complete another iteration
+            if ! info.time.continue_search(info.elapsed()) {
+                break;
             }
-            _ => (),
         }
-        best_move
+
+        result.nodes = info.nodes;
+        result.time = info.elapsed();
+        info.observer.finished(&result);
+        result
     }
 
     pub fn clear_for_search(&mut self, info: &mut SearchInfo) {
@@ -259,6 +257,9 @@ impl Board {
 
         info.stopped = false;
         info.nodes = 0;
+        let root_moves = self.count_legal_moves();
+        info.time.start(root_moves == 1);
+        info.progress.moves_total.store(root_moves as u32, Ordering::Relaxed);
 
         info.fail_high = 0;
         info.fail_high_first = 0;
@@ -269,6 +270,12 @@ impl Board {
 
         let mut depth = depth_in;
 
+        // The previous move may have decided the game by the
+        // variant's rules
+        if let Some(winner) = self.variant_winner() {
+            return self.variant_win_score(winner);
+        }
+
         // Check extension.  See also VICE video 76.  I am somewhat
         // unclear on the different types of check extensions
         // discussed online ("extending check evasion" vs "extending
@@ -277,7 +284,7 @@ impl Board {
         // quiescence (VICE and tscp), or after make_move.  Don't like
         // the idea of doing it after quiescence because then we could
         // enter quiescence while in check.
-        let in_check = self.square_attacked(self.king_sq[self.side], self.side^1);
+        let in_check = self.in_check();
         if in_check {
             depth += 1;
         }
@@ -290,7 +297,14 @@ impl Board {
 
         info.nodes += 1;
 
-        if (self.is_repetition() || self.fifty_move >= 100) && self.ply > 0 {
+        if (self.is_repetition() || self.fifty_move >= 100 || self.is_draw_by_material()) && self.ply > 0 {
+            return 0;
+        }
+
+        // Drawn king and pawn endings are exact, so there is nothing
+        // to search.  Won ones are still searched so that the pawn
+        // makes progress towards promotion.
+        if self.ply > 0 && self.probe_kpk() == Some(false) {
             return 0;
         }
 
@@ -322,12 +336,21 @@ impl Board {
             pick_next_move(imove, &mut move_list);
             let smv = &move_list.moves[imove];
             
+            // The position is restored from a snapshot after the
+            // search of the move, rather than undoing it
+            let state = self.snapshot();
             if ! self.make_move(&smv.mv) {
                 continue;
             }
             legal += 1;
+            if self.ply == 1 {
+                *info.progress.current_move.lock().unwrap() = Some(smv.mv);
+            }
             score = - self.alpha_beta(-beta, -alpha, depth-1, info, true);
-            self.undo_move();
+            self.restore_snapshot(&state);
+            if self.ply == 0 {
+                info.progress.moves_searched.fetch_add(1, Ordering::Relaxed);
+            }
 
             if info.stopped {
                 return 0;
@@ -355,7 +378,7 @@ impl Board {
                     // VICE video 64: mentions prioritizing moves
                     // "nearest to ply", but this seems to be the
                     // opposite of adding depth?
-                    self.search_history[self.pieces[smv.mv.from() as usize] as usize][smv.mv.to() as usize] += depth;
+                    self.search_history[self.moving_piece(&smv.mv) as usize][smv.mv.to() as usize] += depth;
                 }
             }
         }
@@ -363,6 +386,9 @@ impl Board {
         if legal == 0 {
             if in_check {
                 return -MATE + self.ply as i32;
+            } else if self.variant == Variant::Antichess {
+                // Stalemate wins
+                return MATE - self.ply as i32;
             } else {
                 return 0;
             }
@@ -384,19 +410,29 @@ impl Board {
 
         info.nodes += 1;
 
-        if self.is_repetition() || self.fifty_move >= 100 {
+        if let Some(winner) = self.variant_winner() {
+            return self.variant_win_score(winner);
+        }
+
+        if self.is_repetition() || self.fifty_move >= 100 || self.is_draw_by_material() {
             return 0;
         }
 
         let mut alpha = alpha_in;
 
+        // Captures are compulsory in antichess, so standing pat is
+        // only possible without one
+        let forced = self.variant == Variant::Antichess && ! self.generate_all_captures().moves.is_empty();
+
         let mut score = self.evaluate();
-        if score >= beta {
-            return beta;
-        }
-        else if score > alpha {
-            // Standing pat
-            alpha = score;
+        if ! forced {
+            if score >= beta {
+                return beta;
+            }
+            else if score > alpha {
+                // Standing pat
+                alpha = score;
+            }
         }
 
         let mut legal = 0;
@@ -412,12 +448,13 @@ impl Board {
             pick_next_move(imove, &mut move_list);
             let smv = &move_list.moves[imove];
             
+            let state = self.snapshot();
             if ! self.make_move(&smv.mv) {
                 continue;
             }
             legal += 1;
             score = - self.quiescence(-beta, -alpha, info);
-            self.undo_move();
+            self.restore_snapshot(&state);
 
             if info.stopped {
                 return 0;
@@ -444,16 +481,31 @@ impl Board {
         alpha
     }
 
-    fn is_repetition(&self) -> bool {
-        if self.hist_ply <= 0 {
-            return false;
+    // Score for the side to move when a variant's game is over
+    fn variant_win_score(&self, winner: usize) -> i32 {
+        if winner == self.side {
+            MATE - self.ply as i32
+        } else {
+            -MATE + self.ply as i32
         }
-        for i in self.hist_ply-self.fifty_move..self.hist_ply-1 {
-            if self.history[i as usize].hash == self.hash {
-                return true;
+    }
+
+    fn count_legal_moves(&mut self) -> usize {
+        let move_list = self.generate_all_moves();
+        let mut count = 0;
+        for smv in move_list.moves.iter() {
+            if self.make_move(&smv.mv) {
+                count += 1;
+                self.undo_move();
             }
         }
-        false
+        count
+    }
+
+    // Any repetition counts as a draw in the search, as the side that
+    // repeated could repeat again
+    fn is_repetition(&self) -> bool {
+        self.repetitions() > 0
     }
 
     pub fn store_pv_move(&mut self, mv: moves::Move) {
@@ -483,19 +535,22 @@ impl Board {
 
 pub fn benchmark_search(depth: u32) {
     let mut board = Board::from_fen(START_FEN);
-    let mut info = SearchInfo::new(depth, GameMode::Console); 
+    let mut info = SearchInfo::new(depth);
+    info.set_observer(Box::new(ConsoleObserver{show_thinking: true}));
     board.search(&mut info);
 }
 
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc,Mutex};
+    use std::time::Duration;
     use crate::board::*;
     
     #[test]
     fn search_start_depth3() {
         let mut board = Board::from_fen(START_FEN);
-        let mut info = SearchInfo::new(3, GameMode::None); 
+        let mut info = SearchInfo::new(3); 
         board.search(&mut info);
         assert_eq!(board.pv_array[0].to_string(), "d2d4");
         assert_eq!(info.nodes, 657);
@@ -505,9 +560,85 @@ mod tests {
     fn search_wac1_depth3() {
         let wa_c1 = "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1";
         let mut board = Board::from_fen(wa_c1);
-        let mut info = SearchInfo::new(3, GameMode::None); 
+        let mut info = SearchInfo::new(3); 
         board.search(&mut info);
         assert_eq!(board.pv_array[0].to_string(), "f1c4");
         assert_eq!(info.nodes, 8048);
     }
+
+    // Records the depths reported to it
+    struct DepthRecorder(Arc<Mutex<Vec<u32>>>);
+
+    impl SearchObserver for DepthRecorder {
+        fn iteration(&mut self, it: &Iteration) {
+            assert_eq!(it.pv.len() as u32, it.depth);
+            self.0.lock().unwrap().push(it.depth);
+        }
+    }
+
+    #[test]
+    fn observer_and_result() {
+        let depths = Arc::new(Mutex::new(Vec::new()));
+        let mut board = Board::from_fen(START_FEN);
+        let mut info = SearchInfo::new(3);
+        info.set_observer(Box::new(DepthRecorder(depths.clone())));
+        let result = board.search(&mut info);
+        assert_eq!(*depths.lock().unwrap(), vec![1, 2, 3]);
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.nodes, 657);
+        assert_eq!(result.pv.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), vec!["d2d4", "d7d5", "c1e3"]);
+    }
+
+    fn node_clock_info(micros_per_node: u64) -> SearchInfo {
+        let mut info = SearchInfo::new(MAX_DEPTH);
+        info.set_clock(Box::new(NodeClock::new(Duration::from_micros(micros_per_node))));
+        info
+    }
+
+    #[test]
+    fn search_time() {
+        let mut info = node_clock_info(100);
+        info.set_search_time(Some(60_000), None, 30, None);
+        assert_eq!(info.time.soft_limit(), Some(Duration::from_millis(1950)));
+        assert_eq!(info.time.hard_limit(), Some(Duration::from_millis(7800)));
+        info.set_search_time(None, None, 30, None);
+        assert!(! info.time.has_limit());
+    }
+
+    // The soft limit is passed while searching depth 5, so depth 6 is
+    // never started
+    #[test]
+    fn iteration_cutoff() {
+        let mut board = Board::from_fen(START_FEN);
+        let mut info = node_clock_info(200);
+        info.set_search_time(Some(60_000), None, 30, None);
+        let result = board.search(&mut info);
+        assert_eq!(result.best_move.unwrap().to_string(), "e2e4");
+        assert_eq!(info.nodes, 24573);
+        assert!(! info.stopped);
+    }
+
+    // The hard limit aborts depth 5, leaving the move from depth 4
+    #[test]
+    fn hard_limit_stop() {
+        let mut board = Board::from_fen(START_FEN);
+        let mut info = node_clock_info(100);
+        info.set_search_time(None, Some(1000), 30, None);
+        let result = board.search(&mut info);
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d4");
+        assert_eq!(info.nodes, 10001);
+        assert!(info.stopped);
+    }
+
+    #[test]
+    fn stop_flag() {
+        let mut board = Board::from_fen(START_FEN);
+        let mut info = node_clock_info(1);
+        info.stop_flag().store(true, std::sync::atomic::Ordering::Relaxed);
+        let result = board.search(&mut info);
+        // Depth 3 completes before the first checkup
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d4");
+        assert_eq!(info.nodes, 2002);
+        assert!(info.stopped);
+    }
 }
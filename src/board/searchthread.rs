@@ -0,0 +1,89 @@
+// Searching in a background thread.
+//
+// The protocol loops keep reading commands while the engine thinks,
+// so that they can answer isready and ping, and stop the search when
+// asked to.  The search gets its own copy of the board, and reports
+// progress through the observer in its SearchInfo.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::thread::{self,JoinHandle};
+use std::time::{Duration,Instant};
+
+use crate::board::*;
+
+pub struct SearchHandle {
+    stop_flag: Arc<AtomicBool>,
+    progress: Arc<SearchProgress>,
+    start_time: Instant,
+    thread: JoinHandle<SearchResult>,
+}
+
+impl SearchHandle {
+    pub fn start(mut board: Board, mut info: SearchInfo) -> SearchHandle {
+        let stop_flag = info.stop_flag();
+        let progress = info.progress();
+        let thread = thread::spawn(move || board.search(&mut info));
+        SearchHandle {
+            stop_flag: stop_flag,
+            progress: progress,
+            start_time: Instant::now(),
+            thread: thread,
+        }
+    }
+
+    pub fn progress(&self) -> &SearchProgress {
+        &self.progress
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start_time.elapsed()
+    }
+
+    // Ask the search to stop as soon as possible.  The result is
+    // still reported, from the last completed iteration.
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Wait for the search to finish
+    pub fn join(self) -> SearchResult {
+        self.thread.join().expect("search thread panicked")
+    }
+
+    pub fn stop_and_join(self) -> SearchResult {
+        self.stop();
+        self.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_in_background() {
+        let handle = SearchHandle::start(Board::from_fen(START_FEN), SearchInfo::new(3));
+        while ! handle.is_finished() {
+            thread::yield_now();
+        }
+        assert_eq!(handle.progress().depth(), 3);
+        assert_eq!(handle.progress().moves_total(), 20);
+        assert_eq!(handle.progress().moves_left(), 0);
+        let result = handle.join();
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d4");
+        assert_eq!(result.nodes, 657);
+    }
+
+    #[test]
+    fn stop_search() {
+        // Without limits this would take practically forever
+        let handle = SearchHandle::start(Board::from_fen(START_FEN), SearchInfo::new(MAX_DEPTH));
+        let result = handle.stop_and_join();
+        assert!(result.depth < MAX_DEPTH);
+    }
+}
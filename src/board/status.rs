@@ -0,0 +1,233 @@
+// Whether a game is over, and how it ended.
+//
+// The rules are applied in the order in which they end a game: a
+// variant's own win, then checkmate or stalemate, which take
+// precedence over the fifty move rule even on the hundredth half
+// move, then the draws that a player would claim.
+
+use std::fmt;
+
+use crate::board::*;
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum GameStatus {
+    Ongoing,
+    // The side that gave mate
+    Checkmate(usize),
+    Stalemate,
+    FiftyMove,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+    // Won by a variant's own rule, such as three checks in Three-check
+    // or a stalemate in antichess
+    VariantWin(usize),
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+
+    // The winning side, if there is one
+    pub fn winner(&self) -> Option<usize> {
+        match *self {
+            GameStatus::Checkmate(side) | GameStatus::VariantWin(side) => Some(side),
+            _ => None,
+        }
+    }
+
+    pub fn is_draw(&self) -> bool {
+        self.is_over() && self.winner().is_none()
+    }
+
+    // The result as written in PGN and sent to xboard
+    pub fn result(&self) -> &'static str {
+        match self.winner() {
+            Some(WHITE) => "1-0",
+            Some(_) => "0-1",
+            None if self.is_over() => "1/2-1/2",
+            None => "*",
+        }
+    }
+}
+
+// The result with a comment, as xboard expects it: "1-0 {White mates}"
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color = |side: usize| if side == WHITE { "White" } else { "Black" };
+        match *self {
+            GameStatus::Ongoing => write!(f, "*"),
+            GameStatus::Checkmate(side) => write!(f, "{} {{{} mates}}", self.result(), color(side)),
+            GameStatus::VariantWin(side) => write!(f, "{} {{{} wins}}", self.result(), color(side)),
+            GameStatus::Stalemate => write!(f, "1/2-1/2 {{Stalemate}}"),
+            GameStatus::FiftyMove => write!(f, "1/2-1/2 {{Fifty move rule}}"),
+            GameStatus::ThreefoldRepetition => write!(f, "1/2-1/2 {{Threefold repetition}}"),
+            GameStatus::InsufficientMaterial => write!(f, "1/2-1/2 {{Insufficient material}}"),
+        }
+    }
+}
+
+impl Board {
+    pub fn game_status(&mut self) -> GameStatus {
+        if let Some(winner) = self.variant_winner() {
+            return GameStatus::VariantWin(winner);
+        }
+
+        if ! self.has_legal_move() {
+            return if self.in_check() {
+                GameStatus::Checkmate(self.side ^ 1)
+            } else if self.variant == Variant::Antichess {
+                GameStatus::VariantWin(self.side)
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+
+        // Fifty moves by each side without a capture or pawn move
+        if self.fifty_move >= 100 {
+            return GameStatus::FiftyMove;
+        }
+
+        // The third occurrence of the position
+        if self.repetitions() >= 2 {
+            return GameStatus::ThreefoldRepetition;
+        }
+
+        if self.is_dead_position() {
+            return GameStatus::InsufficientMaterial;
+        }
+
+        GameStatus::Ongoing
+    }
+
+    // Earlier occurrences of the current position.  Only positions
+    // since the last capture or pawn move can repeat, and only those
+    // with the same side to move.  Castling rights, en passant and
+    // pockets are part of the hash, so positions that differ in them
+    // don't count.
+    pub(super) fn repetitions(&self) -> usize {
+        let start = self.hist_ply.saturating_sub(self.fifty_move) as usize;
+        self.history[start..].iter().rev().skip(1).step_by(2)
+            .filter(|undo| undo.hash == self.hash)
+            .count()
+    }
+
+    fn has_legal_move(&mut self) -> bool {
+        let move_list = self.generate_all_moves();
+        for smv in move_list.moves.iter() {
+            if self.make_move(&smv.mv) {
+                self.undo_move();
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::*;
+
+    fn status(fen: &str) -> GameStatus {
+        Board::from_fen(fen).game_status()
+    }
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for mv in moves {
+            let mv = board.parse_move(mv).unwrap();
+            assert!(board.make_move(&mv));
+            board.reset_ply();
+        }
+    }
+
+    #[test]
+    fn ongoing() {
+        assert_eq!(status(START_FEN), GameStatus::Ongoing);
+        assert_eq!(status(START_FEN).to_string(), "*");
+        // Ninety-nine half moves are not enough
+        assert_eq!(status("8/8/4k3/8/8/3QK3/8/8 w - - 99 80"), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn checkmate() {
+        let mated = status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(mated, GameStatus::Checkmate(BLACK));
+        assert_eq!(mated.to_string(), "0-1 {Black mates}");
+
+        let mated = status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(mated, GameStatus::Checkmate(WHITE));
+        assert_eq!(mated.to_string(), "1-0 {White mates}");
+
+        // Mate on the hundredth half move wins
+        assert_eq!(status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 90"), GameStatus::Checkmate(WHITE));
+    }
+
+    #[test]
+    fn stalemate() {
+        let stalemate = status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(stalemate, GameStatus::Stalemate);
+        assert_eq!(stalemate.to_string(), "1/2-1/2 {Stalemate}");
+    }
+
+    #[test]
+    fn fifty_move() {
+        let draw = status("8/8/4k3/8/8/3QK3/8/8 w - - 100 80");
+        assert_eq!(draw, GameStatus::FiftyMove);
+        assert_eq!(draw.to_string(), "1/2-1/2 {Fifty move rule}");
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut board = Board::from_fen(START_FEN);
+        let knights = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        // The start position occurs a second time
+        play(&mut board, &knights);
+        assert_eq!(board.game_status(), GameStatus::Ongoing);
+
+        // A third time
+        play(&mut board, &knights);
+        let draw = board.game_status();
+        assert_eq!(draw, GameStatus::ThreefoldRepetition);
+        assert_eq!(draw.to_string(), "1/2-1/2 {Threefold repetition}");
+
+        // A pawn move can't be repeated
+        let mut board = Board::from_fen(START_FEN);
+        play(&mut board, &knights);
+        play(&mut board, &["e2e4", "e7e5"]);
+        play(&mut board, &knights);
+        assert_eq!(board.game_status(), GameStatus::Ongoing);
+
+        // The position after the first move had castling rights, which
+        // the king move lost
+        let mut board = Board::from_fen("r3k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        play(&mut board, &["e1f1", "a8a7", "f1e1", "a7a8", "e1f1", "a8a7", "f1e1", "a7a8"]);
+        assert_eq!(board.game_status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let draw = status("8/8/4k3/8/8/3NK3/8/8 w - - 0 1");
+        assert_eq!(draw, GameStatus::InsufficientMaterial);
+        assert_eq!(draw.to_string(), "1/2-1/2 {Insufficient material}");
+        assert_eq!(status("8/8/4k3/8/8/3RK3/8/8 w - - 0 1"), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn variant_win() {
+        let mut board = Board::from_fen("k7/8/8/8/8/8/8/7K w - - +3+0 0 1");
+        board.set_variant(Variant::ThreeCheck);
+        let win = board.game_status();
+        assert_eq!(win, GameStatus::VariantWin(WHITE));
+        assert_eq!(win.to_string(), "1-0 {White wins}");
+        assert_eq!(win.result(), "1-0");
+
+        // Stalemate wins in antichess
+        let mut board = Board::from_fen("8/8/8/8/8/p7/P7/8 b - - 0 1");
+        board.set_variant(Variant::Antichess);
+        assert_eq!(board.game_status(), GameStatus::VariantWin(BLACK));
+    }
+}
@@ -0,0 +1,302 @@
+// Time management.
+//
+// Each search gets two limits: a soft limit, after which no new
+// iteration is started, and a hard limit, after which the search is
+// aborted even in the middle of an iteration.  The soft limit is
+// adjusted after each completed iteration: it is extended while the
+// best move keeps changing or the score is dropping, and reduced
+// when the same best move has been found for several iterations.
+//
+// Elapsed time is passed in by the caller rather than read from the
+// system clock, so that decisions can be tested without waiting.
+
+use std::time::Duration;
+
+use crate::moves;
+
+pub const DEFAULT_MOVE_OVERHEAD: u64 = 50;
+
+// Lower limit to make sure we have enough time to at least find a move
+const MIN_TIME: Duration = Duration::from_millis(50);
+
+// The hard limit is this many times the base allocation, but never
+// more than the time left
+const HARD_LIMIT_FACTOR: u32 = 4;
+
+// Stop early once the best move has been stable for this many
+// iterations
+const STABLE_ITERATIONS: u32 = 6;
+
+// Score drops (in centipawns) that extend the search
+const SCORE_DROP: i32 = 30;
+const LARGE_SCORE_DROP: i32 = 80;
+
+pub struct TimeManager {
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    // Time reserved for communication delays, in milliseconds
+    move_overhead: u64,
+
+    // State of the current search
+    best_move: Option<moves::Move>,
+    stable_iterations: u32,
+    // Number of recent iterations in which the best move changed
+    changes: u32,
+    last_score: Option<i32>,
+    score_drop: i32,
+    single_reply: bool,
+}
+
+impl TimeManager {
+    pub fn new() -> TimeManager {
+        TimeManager {
+            soft_limit: None,
+            hard_limit: None,
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+
+            best_move: None,
+            stable_iterations: 0,
+            changes: 0,
+            last_score: None,
+            score_drop: 0,
+            single_reply: false,
+        }
+    }
+
+    pub fn set_move_overhead(&mut self, overhead: u64) {
+        self.move_overhead = overhead;
+    }
+
+    // Set the limits for a new search from the clock conditions.
+    //
+    // If time_left and move_time are both None, there is no limit.
+    // move_time should be mutually exclusive with time_left and inc.
+    pub fn set_limits(&mut self, time_left: Option<u64>, move_time: Option<u64>, moves_to_go: u32, increment: Option<u64>) {
+        self.reset();
+
+        let overhead = Duration::from_millis(self.move_overhead);
+        if let Some(mt) = move_time {
+            // Use all of the given time, but no more
+            let limit = std::cmp::max(MIN_TIME, Duration::from_millis(mt).saturating_sub(overhead));
+            self.soft_limit = Some(limit);
+            self.hard_limit = Some(limit);
+        } else if let Some(tl) = time_left {
+            let moves_to_go = std::cmp::max(1, moves_to_go);
+            let available = Duration::from_millis(tl).saturating_sub(overhead);
+
+            // Don't add the full increment for the current move.
+            // With a large increment relative to time left, the
+            // time allocated to thinking could exceed the time left.
+            let inc = increment.unwrap_or(0);
+            let base = Duration::from_millis(tl + (moves_to_go as u64 - 1) * inc) / moves_to_go;
+            let base = std::cmp::min(base.saturating_sub(overhead), available);
+
+            let hard = std::cmp::min(base * HARD_LIMIT_FACTOR, available);
+            self.soft_limit = Some(std::cmp::max(MIN_TIME, base));
+            self.hard_limit = Some(std::cmp::max(MIN_TIME, hard));
+        } else {
+            self.soft_limit = None;
+            self.hard_limit = None;
+        }
+    }
+
+    pub fn unset_limits(&mut self) {
+        self.reset();
+        self.soft_limit = None;
+        self.hard_limit = None;
+    }
+
+    fn reset(&mut self) {
+        self.best_move = None;
+        self.stable_iterations = 0;
+        self.changes = 0;
+        self.last_score = None;
+        self.score_drop = 0;
+        self.single_reply = false;
+    }
+
+    pub fn has_limit(&self) -> bool {
+        self.hard_limit.is_some()
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    // Forget the progress of the previous search.  With only one
+    // legal move there is nothing to think about.
+    pub fn start(&mut self, single_reply: bool) {
+        self.reset();
+        self.single_reply = single_reply;
+    }
+
+    // Record the result of a completed iteration
+    pub fn update(&mut self, best_move: moves::Move, score: i32) {
+        if self.best_move == Some(best_move) {
+            self.stable_iterations += 1;
+            self.changes /= 2;
+        } else {
+            if self.best_move.is_some() {
+                self.changes += 1;
+            }
+            self.best_move = Some(best_move);
+            self.stable_iterations = 0;
+        }
+        if let Some(last) = self.last_score {
+            self.score_drop = last - score;
+        }
+        self.last_score = Some(score);
+    }
+
+    // The soft limit adjusted for the progress of the search so far
+    pub fn adjusted_soft_limit(&self) -> Option<Duration> {
+        let soft = self.soft_limit?;
+        let hard = self.hard_limit?;
+
+        // Percentages of the base allocation
+        let mut scale = 100;
+        if self.changes > 0 {
+            scale += 50 * std::cmp::min(self.changes, 2);
+        } else if self.stable_iterations >= STABLE_ITERATIONS {
+            scale = 50;
+        }
+        if self.score_drop >= LARGE_SCORE_DROP {
+            scale += 100;
+        } else if self.score_drop >= SCORE_DROP {
+            scale += 50;
+        }
+
+        Some(std::cmp::min(hard, soft * scale / 100))
+    }
+
+    // Whether to start another iteration
+    pub fn continue_search(&self, elapsed: Duration) -> bool {
+        if self.single_reply && self.best_move.is_some() && self.has_limit() {
+            return false;
+        }
+        match self.adjusted_soft_limit() {
+            Some(limit) => elapsed < limit,
+            None => true,
+        }
+    }
+
+    // Whether to abort the current iteration
+    pub fn hard_stop(&self, elapsed: Duration) -> bool {
+        match self.hard_limit {
+            Some(limit) => elapsed > limit,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn moves() -> (moves::Move, moves::Move) {
+        let board = Board::from_fen(crate::board::START_FEN);
+        (board.parse_move("e2e4").unwrap(), board.parse_move("d2d4").unwrap())
+    }
+
+    #[test]
+    fn limits() {
+        let mut tm = TimeManager::new();
+        tm.set_limits(Some(60_000), None, 30, None);
+        assert_eq!(tm.soft_limit(), Some(ms(1950)));
+        assert_eq!(tm.hard_limit(), Some(ms(7800)));
+
+        // Last move before the time control can't use more than is left
+        tm.set_limits(Some(1000), None, 1, None);
+        assert_eq!(tm.soft_limit(), Some(ms(950)));
+        assert_eq!(tm.hard_limit(), Some(ms(950)));
+
+        tm.set_limits(None, Some(1000), 30, None);
+        assert_eq!(tm.soft_limit(), Some(ms(950)));
+        assert_eq!(tm.hard_limit(), Some(ms(950)));
+
+        tm.set_limits(None, None, 30, None);
+        assert!(! tm.has_limit());
+        assert!(tm.continue_search(ms(1_000_000)));
+        assert!(! tm.hard_stop(ms(1_000_000)));
+    }
+
+    #[test]
+    fn move_overhead() {
+        let mut tm = TimeManager::new();
+        tm.set_move_overhead(200);
+        tm.set_limits(None, Some(1000), 30, None);
+        assert_eq!(tm.hard_limit(), Some(ms(800)));
+        // Never below the minimum
+        tm.set_limits(Some(100), None, 30, None);
+        assert_eq!(tm.soft_limit(), Some(MIN_TIME));
+    }
+
+    #[test]
+    fn soft_and_hard_limits() {
+        let mut tm = TimeManager::new();
+        tm.set_limits(Some(60_000), None, 30, None);
+        let (e4, _) = moves();
+        tm.update(e4, 20);
+        assert!(tm.continue_search(ms(1900)));
+        assert!(! tm.continue_search(ms(2000)));
+        assert!(! tm.hard_stop(ms(2000)));
+        assert!(tm.hard_stop(ms(7801)));
+    }
+
+    #[test]
+    fn best_move_changes_extend() {
+        let mut tm = TimeManager::new();
+        tm.set_limits(Some(60_000), None, 30, None);
+        let (e4, d4) = moves();
+        tm.update(e4, 20);
+        tm.update(d4, 20);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(2925)));
+        tm.update(e4, 20);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(3900)));
+        // Extensions settle down once the best move is stable again
+        tm.update(e4, 20);
+        tm.update(e4, 20);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(1950)));
+    }
+
+    #[test]
+    fn score_drop_extends() {
+        let mut tm = TimeManager::new();
+        tm.set_limits(Some(60_000), None, 30, None);
+        let (e4, _) = moves();
+        tm.update(e4, 50);
+        tm.update(e4, 10);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(2925)));
+        tm.update(e4, -100);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(3900)));
+        tm.update(e4, -100);
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(1950)));
+    }
+
+    #[test]
+    fn early_exit() {
+        let mut tm = TimeManager::new();
+        tm.set_limits(Some(60_000), None, 30, None);
+        let (e4, _) = moves();
+        for _ in 0..=STABLE_ITERATIONS {
+            tm.update(e4, 20);
+        }
+        assert_eq!(tm.adjusted_soft_limit(), Some(ms(975)));
+
+        // Only one legal move
+        tm.set_limits(Some(60_000), None, 30, None);
+        tm.start(true);
+        assert!(tm.continue_search(ms(0)));
+        tm.update(e4, 20);
+        assert!(! tm.continue_search(ms(0)));
+    }
+}
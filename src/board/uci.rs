@@ -1,13 +1,18 @@
 use std::thread;
 use std::sync::mpsc;
+use std::collections::VecDeque;
+use std::time::Duration;
 use std::io::{self, Write};
+use std::sync::{Arc,Mutex};
 
 use crate::board::*;
 use crate::version::PROGRAM_NAME;
 
 impl Board {
     // go depth <> wtime <> btime <> binc <> winc <> movetime <> movestogo <>
-    pub fn parse_go(&mut self, line: &str, info: &mut SearchInfo) {
+    //
+    // Sets the search limits in info, ready to start the search
+    pub fn parse_go(&self, line: &str, info: &mut SearchInfo) {
         let mut depth = MAX_DEPTH; // Default max depth
         let mut moves_to_go = 30;  // Default value if not provided
         let mut move_time: Option<u64> = None;
@@ -46,11 +51,6 @@ impl Board {
         
         // Safe to call if time and move_time are both None
         info.set_search_time(time, move_time, moves_to_go, inc);
-        if time == None && move_time == None {
-            println!("depth:{}", depth);
-        }
-
-        self.search(info);
     }
 
     // position startpos
@@ -93,9 +93,63 @@ impl Board {
 fn uci_ok() {
     println!("id name {}", PROGRAM_NAME);
     println!("id author John McFarland");
+    println!("option name UseNNUE type check default false");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!("option name Best Book Move type check default false");
+    println!("option name UCI_Chess960 type check default false");
+    let vars: Vec<String> = Variant::ALL.iter().map(|variant| format!("var {}", variant.name())).collect();
+    println!("option name UCI_Variant type combo default {} {}", Variant::Standard.name(), vars.join(" "));
+    println!("option name Move Overhead type spin default {} min 0 max 5000", timeman::DEFAULT_MOVE_OVERHEAD);
+    println!("option name Resign Score type spin default {} min 0 max 30000", adjudicate::DEFAULT_RESIGN_SCORE);
+    println!("option name Resign Moves type spin default {} min 0 max 100", adjudicate::DEFAULT_RESIGN_MOVES);
+    println!("option name Draw Score type spin default {} min 0 max 1000", adjudicate::DEFAULT_DRAW_SCORE);
+    println!("option name Draw Moves type spin default {} min 0 max 100", adjudicate::DEFAULT_DRAW_MOVES);
+    println!("option name Draw Min Moves type spin default {} min 0 max 1000", adjudicate::DEFAULT_DRAW_MIN_MOVES);
     println!("uciok");
 }
 
+// setoption name <id> [value <x>]
+//
+// Returns the option name and value, either of which may contain
+// spaces
+fn parse_setoption(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end();
+    let name_start = line.find("name ")? + 5;
+    match line.find(" value ") {
+        Some(i) if i > name_start => Some((line[name_start..i].trim(), line[i+7..].trim())),
+        _ => Some((line[name_start..].trim(), "")),
+    }
+}
+
+// Evaluation settings that can be changed with setoption
+struct EvalOptions {
+    use_nnue: bool,
+    eval_file: String,
+}
+
+impl EvalOptions {
+    // Load the network if enabled, falling back to the handcrafted
+    // evaluation if it can't be loaded
+    fn apply(&self, board: &mut Board) {
+        if ! self.use_nnue || self.eval_file.is_empty() {
+            board.set_network(None);
+            return;
+        }
+        match Network::load(&self.eval_file) {
+            Ok(net) => {
+                println!("info string loaded network {}", self.eval_file);
+                board.set_network(Some(Arc::new(net)));
+            }
+            Err(e) => {
+                println!("info string failed to load network {}: {}", self.eval_file, e);
+                board.set_network(None);
+            }
+        }
+    }
+}
+
 pub fn uci_loop() {
 
     let (tx, rx) = mpsc::channel();
@@ -109,46 +163,174 @@ pub fn uci_loop() {
     });
 
     let mut board = Board::new();
-    let mut info = SearchInfo::new(5, GameMode::Uci);
-    // Store a receiver in the search info so that it can catch "stop"
-    // messages
-    info.set_receiver(&rx);
+    let mut eval_options = EvalOptions{use_nnue: false, eval_file: String::new()};
+    let mut move_overhead = timeman::DEFAULT_MOVE_OVERHEAD;
+    // Shared with the search's observer, which decides after each
+    // search
+    let adjudicator = Arc::new(Mutex::new(Adjudicator::new()));
+    let mut book_options = BookOptions::new();
+    // Castling is written as the king capturing its rook in Chess960
+    let mut castle_notation = CastleNotation::KingMove;
+    // The search runs in the background so that commands are still
+    // read while thinking
+    let mut search: Option<SearchHandle> = None;
+    // Commands that change the state, held back until the search
+    // finishes
+    let mut pending: VecDeque<String> = VecDeque::new();
 
     uci_ok();
 
     loop {
         io::stdout().flush().unwrap();
 
-        match rx.recv() {
-            Ok(input) => {
-                if input.len() == 1 && input.chars().nth(0).unwrap() == '\n' {
-                    continue;
-                }
-
-                if input.starts_with('\n') {
-                    continue;
-                } else if input.starts_with("isready") {
-                    println!("readyok");
-                } else if input.starts_with("position") {
-                    board = board.parse_pos(&input);
-                } else if input.starts_with("ucinewgame") {
-                    board = board.parse_pos("position startpos\n");
-                } else if input.starts_with("go") {
-                    board.parse_go(&input, &mut info);
-                } else if input.starts_with("uci") {
-                    uci_ok();
-                } else if input.starts_with("quit") {
-                    break;
+        let searching = search.as_ref().is_some_and(|handle| ! handle.is_finished());
+        let input = if ! searching && ! pending.is_empty() {
+            pending.pop_front().unwrap()
+        } else if searching && ! pending.is_empty() {
+            // Wake up now and then to see whether the search has
+            // finished, so that the held back commands are handled
+            match rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(input) => input,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => panic!("stdin channel closed"),
+            }
+        } else {
+            match rx.recv() {
+                Ok(input) => input,
+                _ => {
+                    panic!("stdin channel closed");
+                }
+            }
+        };
+
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        // Commands that don't change the state are answered while
+        // searching.  Waiting for the search instead could wait
+        // forever, as an infinite search only ends with stop.
+        if searching {
+            match input.split_whitespace().next() {
+                Some("isready") => println!("readyok"),
+                Some("stop") => if let Some(handle) = search.take() {
+                    handle.stop_and_join();
                 }
-                if info.quit {
+                Some("quit") => {
+                    if let Some(handle) = search.take() {
+                        handle.stop_and_join();
+                    }
                     break;
                 }
+                Some("uci") => uci_ok(),
+                Some("eval") => println!("{}", board.evaluate_breakdown()),
+                // There is no debug output or pondering
+                Some("debug") | Some("ponderhit") => (),
+                _ => pending.push_back(input),
             }
-            _ => {
-                panic!("stdin channel closed");
+            continue;
+        }
+
+        // The search finished by itself
+        if let Some(handle) = search.take() {
+            handle.join();
+        }
+
+        if input.starts_with("isready") {
+            println!("readyok");
+            continue;
+        } else if input.starts_with("stop") {
+            continue;
+        }
+
+        if input.starts_with("position") {
+            board = board.parse_pos(&input);
+        } else if input.starts_with("ucinewgame") {
+            board = board.parse_pos("position startpos\n");
+            adjudicator.lock().unwrap().reset();
+        } else if input.starts_with("go") {
+            let move_format = board.move_format(castle_notation);
+            if let Some(mv) = book_options.probe(&mut board) {
+                println!("bestmove {}", move_format.format(&mv));
+                continue;
+            }
+            let mut info = SearchInfo::new(MAX_DEPTH);
+            info.set_observer(Box::new(UciObserver{adjudicator: adjudicator.clone(), move_number: board.move_number(), move_format: move_format}));
+            info.time.set_move_overhead(move_overhead);
+            board.parse_go(&input, &mut info);
+            search = Some(SearchHandle::start(board.clone(), info));
+        } else if input.starts_with("setoption") {
+            match parse_setoption(&input) {
+                Some(("UseNNUE", value)) => {
+                    eval_options.use_nnue = value == "true";
+                    eval_options.apply(&mut board);
+                }
+                Some(("EvalFile", value)) => {
+                    eval_options.eval_file = if value == "<empty>" { String::new() } else { value.to_string() };
+                    eval_options.apply(&mut board);
+                }
+                Some(("OwnBook", value)) => {
+                    book_options.own_book = value == "true";
+                }
+                Some(("BookFile", value)) => {
+                    let path = if value == "<empty>" { "" } else { value };
+                    match book_options.load(path) {
+                        Ok(()) => if let Some(book) = book_options.book() {
+                            println!("info string loaded book {} with {} entries", path, book.len());
+                        }
+                        Err(e) => println!("info string failed to load book {}: {}", path, e),
+                    }
+                }
+                Some(("Best Book Move", value)) => {
+                    book_options.selection = if value == "true" { BookSelection::Best } else { BookSelection::Weighted };
+                }
+                Some(("UCI_Chess960", value)) => {
+                    castle_notation = if value == "true" { CastleNotation::KingTakesRook } else { CastleNotation::KingMove };
+                }
+                Some(("UCI_Variant", value)) => {
+                    match Variant::from_name(value) {
+                        Some(variant) => board.set_variant(variant),
+                        None => println!("info string unsupported variant {}", value),
+                    }
+                }
+                Some(("Move Overhead", value)) => {
+                    if let Ok(overhead) = value.parse() {
+                        move_overhead = overhead;
+                    }
+                }
+                Some((name @ ("Resign Score" | "Resign Moves" | "Draw Score" | "Draw Moves" | "Draw Min Moves"), value)) => {
+                    let mut adj = adjudicator.lock().unwrap();
+                    match name {
+                        "Resign Score" => adj.resign_score = value.parse().unwrap_or(adj.resign_score),
+                        "Resign Moves" => adj.resign_moves = value.parse().unwrap_or(adj.resign_moves),
+                        "Draw Score" => adj.draw_score = value.parse().unwrap_or(adj.draw_score),
+                        "Draw Moves" => adj.draw_moves = value.parse().unwrap_or(adj.draw_moves),
+                        _ => adj.draw_min_moves = value.parse().unwrap_or(adj.draw_min_moves),
+                    }
+                }
+                _ => (),
             }
+        } else if input.starts_with("eval") {
+            // Debugging extension, not part of the UCI protocol
+            println!("{}", board.evaluate_breakdown());
+        } else if input.starts_with("uci") {
+            uci_ok();
+        } else if input.starts_with("quit") {
+            break;
         }
-        
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setoption() {
+        assert_eq!(parse_setoption("setoption name UseNNUE value true\n"), Some(("UseNNUE", "true")));
+        assert_eq!(parse_setoption("setoption name EvalFile value nets/my net.nnue\n"), Some(("EvalFile", "nets/my net.nnue")));
+        assert_eq!(parse_setoption("setoption name Clear Hash\n"), Some(("Clear Hash", "")));
+        assert_eq!(parse_setoption("setoption\n"), None);
+    }
+}
@@ -0,0 +1,271 @@
+// Chess variants.
+//
+// A variant changes how the game is won, which moves are legal, and
+// may keep extra state on the board:
+//
+// * Three-check: giving a third check wins.  The checks given by each
+//   side are counted on the board, kept in Undo and hashed.
+// * King of the Hill: bringing the king to one of the four centre
+//   squares wins.
+// * Atomic: a capture explodes the capturing piece and all pieces
+//   other than pawns next to the capture square.  Exploding the
+//   other king wins, kings can't capture, and kings that touch can't
+//   give check.
+// * Crazyhouse: captured pieces can be dropped back on the board.
+//   The pockets are kept on the board (see crazyhouse.rs).
+// * Antichess: captures are compulsory, and losing all pieces or
+//   being stalemated wins.  The king is an ordinary piece, so there
+//   is no check or castling (see antichess.rs).
+//
+// Chess960 is not a variant here, as it only changes the start
+// position and castling, which works the same way for all variants.
+
+use crate::board::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Variant {
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Atomic,
+    Crazyhouse,
+    Antichess,
+}
+
+// d4, e4, d5 and e5
+const CENTRE: u64 = 0x0000_0018_1800_0000;
+
+// Bonus for the number of checks given in Three-check
+const CHECK_BONUS: [i32; 4] = [0, 150, 500, 0];
+// Bonus for each step the king is closer to the centre in King of the
+// Hill
+const HILL_STEP_BONUS: i32 = 30;
+
+impl Variant {
+    pub const ALL: [Variant; 6] = [Variant::Standard, Variant::ThreeCheck, Variant::KingOfTheHill, Variant::Atomic, Variant::Crazyhouse, Variant::Antichess];
+
+    // Name used by UCI_Variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Atomic => "atomic",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Antichess => "antichess",
+        }
+    }
+
+    // Name used by the xboard variant command
+    pub fn xboard_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "normal",
+            // Suicide in xboard is the same, except for stalemate
+            Variant::Antichess => "giveaway",
+            _ => self.name(),
+        }
+    }
+
+    // Accepts the UCI and xboard names
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "standard" | "normal" => Some(Variant::Standard),
+            "giveaway" => Some(Variant::Antichess),
+            _ => Variant::ALL.iter().find(|variant| variant.name() == name).copied(),
+        }
+    }
+}
+
+// Checks given by each side, from the check counts field of a FEN:
+// "3+3" as checks remaining (lichess), or "+0+0" as checks given
+pub(super) fn parse_checks(field: &str) -> Option<[u8; 2]> {
+    let (given, field) = match field.strip_prefix('+') {
+        Some(field) => (true, field),
+        None => (false, field),
+    };
+    let (white, black) = field.split_once('+')?;
+    let mut checks = [white.parse::<u8>().ok()?, black.parse::<u8>().ok()?];
+    for count in checks.iter_mut() {
+        if *count > 3 {
+            return None;
+        }
+        if ! given {
+            *count = 3 - *count;
+        }
+    }
+    Some(checks)
+}
+
+impl Board {
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    // The side that has won by the variant's own rules, if the game
+    // is over.  Checkmate and stalemate are found by searching for a
+    // legal move as usual.  In antichess the winner is the side to
+    // move, as the other side has just captured its last piece.  In
+    // the other variants it is the side that has just moved.
+    pub fn variant_winner(&self) -> Option<usize> {
+        match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::ThreeCheck => [WHITE, BLACK].iter().copied().find(|&color| self.checks[color] >= 3),
+            Variant::KingOfTheHill => [WHITE, BLACK].iter().copied().find(|&color| {
+                self.king_sq[color] != Position::NONE as Square && CENTRE & (1 << self.king_sq[color]) != 0
+            }),
+            Variant::Atomic => [WHITE, BLACK].iter().copied().find(|&color| self.king_sq[color ^ 1] == Position::NONE as Square),
+            Variant::Antichess => [WHITE, BLACK].iter().copied().find(|&color| ! self.bb_sides[color].nonzero()),
+        }
+    }
+
+    // Whether the side to move is in check
+    pub fn in_check(&self) -> bool {
+        self.king_attacked(self.side)
+    }
+
+    // Whether a side's king is attacked, so that a move leaving it
+    // attacked is illegal.  An exploded king counts as attacked, and
+    // kings are never attacked in antichess.
+    pub(super) fn king_attacked(&self, side: usize) -> bool {
+        if self.variant == Variant::Antichess {
+            return false;
+        }
+        let king = self.king_sq[side];
+        if king == Position::NONE as Square {
+            return true;
+        }
+        self.king_square_attacked(king, side ^ 1, self.bb_sides[BOTH].0)
+    }
+
+    // Whether the king would be attacked on a square, with the given
+    // occupancy.  In atomic chess the king can't be attacked next to
+    // the other king, or once the other king has exploded.
+    pub(super) fn king_square_attacked(&self, sq: Square, side: usize, occupancy: u64) -> bool {
+        if self.variant == Variant::Atomic {
+            let other_king = self.king_sq[side];
+            if other_king == Position::NONE as Square || KING_MOVES[sq as usize].0 & (1 << other_king) != 0 {
+                return false;
+            }
+        }
+        self.square_attacked_with(sq, side, occupancy)
+    }
+
+    // Three-check counts in FEN, as checks remaining
+    pub(super) fn checks_fen(&self) -> String {
+        format!("{}+{}", 3 - self.checks[WHITE], 3 - self.checks[BLACK])
+    }
+
+    // Variant specific evaluation, for side to move
+    pub(super) fn evaluate_variant(&self) -> i32 {
+        let score = match self.variant {
+            Variant::Standard | Variant::Atomic => 0,
+            Variant::ThreeCheck => CHECK_BONUS[self.checks[WHITE] as usize] - CHECK_BONUS[self.checks[BLACK] as usize],
+            Variant::KingOfTheHill => {
+                // Distance in king moves to the nearest centre square
+                let distance = |sq: Square| {
+                    let file = (sq % 8) as i32;
+                    let rank = (sq / 8) as i32;
+                    std::cmp::max((file - 3).max(4 - file), (rank - 3).max(4 - rank)) - 1
+                };
+                HILL_STEP_BONUS * (distance(self.king_sq[BLACK]) - distance(self.king_sq[WHITE]))
+            }
+            Variant::Crazyhouse => self.pocket_material(),
+            // Evaluated separately, as the usual evaluation doesn't
+            // apply at all
+            Variant::Antichess => 0,
+        };
+        if self.side == WHITE { score } else { -score }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for variant in Variant::ALL {
+            assert_eq!(Variant::from_name(variant.name()), Some(variant));
+            assert_eq!(Variant::from_name(variant.xboard_name()), Some(variant));
+        }
+        assert_eq!(Variant::from_name("horde"), None);
+    }
+
+    #[test]
+    fn check_counts() {
+        assert_eq!(parse_checks("3+3"), Some([0, 0]));
+        assert_eq!(parse_checks("1+2"), Some([2, 1]));
+        assert_eq!(parse_checks("+1+0"), Some([1, 0]));
+        assert_eq!(parse_checks("4+0"), None);
+        assert_eq!(parse_checks("-"), None);
+
+        let mut board = Board::from_fen("rnbqkbnr/ppp1pppp/8/1B1p4/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 2");
+        board.set_variant(Variant::ThreeCheck);
+        assert_eq!(board.to_fen(), "rnbqkbnr/ppp1pppp/8/1B1p4/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 2");
+        assert!(board.in_check());
+
+        // A second check, and taking it back
+        let hash = board.get_position_hash();
+        let mv = board.parse_move("c7c6").unwrap();
+        assert!(board.make_move(&mv));
+        let mv = board.parse_move("b5c6").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.checks, [2, 0]);
+        assert_eq!(board.hash, board.get_position_hash());
+        board.undo_move();
+        board.undo_move();
+        assert_eq!(board.checks, [1, 0]);
+        assert_eq!(board.hash, hash);
+    }
+
+    #[test]
+    fn winners() {
+        let mut board = Board::from_fen("8/8/8/4K3/8/8/8/k7 b - - 0 1");
+        assert_eq!(board.variant_winner(), None);
+        board.set_variant(Variant::KingOfTheHill);
+        assert_eq!(board.variant_winner(), Some(WHITE));
+        assert!(board.generate_all_moves().moves.is_empty());
+
+        let mut board = Board::from_fen("k7/8/8/8/8/8/8/7K w - - +3+0 0 1");
+        board.set_variant(Variant::ThreeCheck);
+        assert_eq!(board.variant_winner(), Some(WHITE));
+    }
+
+    #[test]
+    fn atomic_captures() {
+        let mut board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1");
+        board.set_variant(Variant::Atomic);
+        let fen = board.to_fen();
+        let hash = board.hash;
+
+        // The knight and pawn explode, but not the pawns around them
+        let mv = board.parse_move("f3e5").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppp1ppp/8/8/4P3/8/PPPP1PPP/RNBQKB1R b KQkq - 0 1");
+        board.undo_move();
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.hash, hash);
+
+        // Exploding the king wins, even when it leaves the own king
+        // in check
+        let mut board = Board::from_fen("4k3/3p4/8/8/8/8/4r3/3QK3 w - - 0 1");
+        board.set_variant(Variant::Atomic);
+        assert!(board.in_check());
+        let mv = board.parse_move("d1d7").unwrap();
+        assert!(board.make_move(&mv));
+        assert_eq!(board.variant_winner(), Some(WHITE));
+        assert!(board.generate_all_moves().moves.is_empty());
+        board.undo_move();
+
+        // Kings can't capture, and touching kings can't give check
+        let mut board = Board::from_fen("8/8/8/8/8/8/2k1n3/3K3r w - - 0 1");
+        board.set_variant(Variant::Atomic);
+        assert!(! board.in_check());
+        let mv = board.parse_move("d1e2").unwrap();
+        assert!(! board.make_move(&mv));
+    }
+}
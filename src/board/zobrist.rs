@@ -0,0 +1,126 @@
+// Zobrist hash keys.
+//
+// All boards share one table of keys, generated from a seed by a
+// small deterministic generator (SplitMix64), so that hashes are
+// comparable between boards and reproducible between runs.  The
+// seed can be changed with set_zobrist_seed, but only before the
+// keys are first used.
+
+use std::sync::OnceLock;
+
+use crate::pieces::NUM_PIECE_TYPES_BOTH;
+use super::BOARD_SQ_NUM;
+use super::crazyhouse::POCKET_MAX;
+
+pub const DEFAULT_ZOBRIST_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+static KEYS: OnceLock<HashKeys> = OnceLock::new();
+
+pub struct HashKeys {
+    pub seed: u64,
+    // Hashing also includes EMPTY pieces, used for the en passant
+    // square
+    pub piece_keys: [[u64; BOARD_SQ_NUM]; NUM_PIECE_TYPES_BOTH+1],
+    pub side_key: u64,
+    pub castle_keys: [u64; 16],
+    // Checks given by each side in Three-check.  Keys for zero checks
+    // are not used, so that hashes of standard positions are unchanged.
+    pub check_keys: [[u64; 4]; 2],
+    // Number of each piece in the pockets in Crazyhouse, with keys
+    // for empty pockets unused as for checks
+    pub pocket_keys: [[u64; POCKET_MAX as usize + 1]; NUM_PIECE_TYPES_BOTH],
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl HashKeys {
+    fn generate(seed: u64) -> HashKeys {
+        let mut rng = SplitMix64(seed);
+        let mut keys = HashKeys {
+            seed: seed,
+            piece_keys: [[0; BOARD_SQ_NUM]; NUM_PIECE_TYPES_BOTH+1],
+            side_key: 0,
+            castle_keys: [0; 16],
+            check_keys: [[0; 4]; 2],
+            pocket_keys: [[0; POCKET_MAX as usize + 1]; NUM_PIECE_TYPES_BOTH],
+        };
+
+        keys.side_key = rng.next();
+        for piece_keys in keys.piece_keys.iter_mut() {
+            for key in piece_keys.iter_mut() {
+                *key = rng.next();
+            }
+        }
+        for key in keys.castle_keys.iter_mut() {
+            *key = rng.next();
+        }
+        for check_keys in keys.check_keys.iter_mut() {
+            for key in check_keys.iter_mut() {
+                *key = rng.next();
+            }
+        }
+        for pocket_keys in keys.pocket_keys.iter_mut() {
+            for key in pocket_keys.iter_mut() {
+                *key = rng.next();
+            }
+        }
+
+        keys
+    }
+}
+
+// The shared keys, generated with the default seed on first use if
+// no seed has been set
+pub fn hash_keys() -> &'static HashKeys {
+    KEYS.get_or_init(|| HashKeys::generate(DEFAULT_ZOBRIST_SEED))
+}
+
+// Generate the keys from a seed.  Returns false if the keys have
+// already been generated with another seed.
+pub fn set_zobrist_seed(seed: u64) -> bool {
+    KEYS.get_or_init(|| HashKeys::generate(seed)).seed == seed
+}
+
+pub fn zobrist_seed() -> u64 {
+    hash_keys().seed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board,START_FEN};
+
+    #[test]
+    fn reproducible_keys() {
+        let a = HashKeys::generate(1);
+        let b = HashKeys::generate(1);
+        let c = HashKeys::generate(2);
+        assert_eq!(a.piece_keys, b.piece_keys);
+        assert_eq!(a.side_key, b.side_key);
+        assert_ne!(a.side_key, c.side_key);
+        // First output of SplitMix64 for seed 0
+        assert_eq!(HashKeys::generate(0).side_key, 0xe220a8397b1dcdaf);
+    }
+
+    #[test]
+    fn shared_keys() {
+        let board = Board::from_fen(START_FEN);
+        let other = Board::new().update_from_fen(START_FEN);
+        assert_eq!(board.get_position_hash(), other.get_position_hash());
+        assert_eq!(board.get_position_hash(), 0x155fe59a2e76955d);
+
+        // Too late to change the seed
+        assert!(! set_zobrist_seed(zobrist_seed() + 1));
+        assert!(set_zobrist_seed(DEFAULT_ZOBRIST_SEED));
+    }
+}
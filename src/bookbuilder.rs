@@ -0,0 +1,189 @@
+// Building Polyglot opening books from games.
+//
+// Each game is replayed up to a maximum ply, and the result is
+// recorded for every (position, move) pair from the point of view of
+// the side making the move.  Moves that were played in enough games
+// and scored well enough are written to the book, weighted by
+// 2 * wins + draws.
+
+use std::collections::HashMap;
+
+use crate::board::{Board,Book,BookEntry,START_FEN};
+use crate::pgn::{PgnGame,GameResult};
+use crate::pieces::WHITE;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct MoveStats {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+}
+
+impl MoveStats {
+    // Score from 0 to 1
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games as f64
+    }
+
+    fn weight(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+pub struct BookBuilder {
+    max_ply: usize,
+    // Keyed by Polyglot position key and book move
+    stats: HashMap<(u64, u16), MoveStats>,
+    games: u64,
+    skipped: u64,
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize) -> BookBuilder {
+        BookBuilder{max_ply: max_ply, stats: HashMap::new(), games: 0, skipped: 0}
+    }
+
+    // Games used
+    pub fn games(&self) -> u64 {
+        self.games
+    }
+
+    // Games without a result, or that couldn't be set up or replayed
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn positions(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn add_game(&mut self, game: &PgnGame) {
+        let result = match game.result {
+            Some(result) => result,
+            None => {
+                self.skipped += 1;
+                return;
+            }
+        };
+        let fen = match game.tag("FEN") {
+            Some(fen) => fen,
+            None => START_FEN,
+        };
+        if game.tag("Variant").is_some_and(|variant| variant != "Standard") {
+            self.skipped += 1;
+            return;
+        }
+        let mut board = match Board::try_from_fen(fen) {
+            Some(board) => board,
+            None => {
+                self.skipped += 1;
+                return;
+            }
+        };
+
+        // The moves are recorded only once the whole game has been
+        // replayed, so that a skipped game leaves no stats behind
+        let mut played = Vec::new();
+        for san in game.moves.iter().take(self.max_ply) {
+            let mv = match board.parse_san(san) {
+                Some(mv) => mv,
+                None => {
+                    self.skipped += 1;
+                    return;
+                }
+            };
+            played.push((board.polyglot_key(), board.encode_book_move(&mv), board.side));
+            board.make_move(&mv);
+            board.reset_ply();
+        }
+
+        for (key, book_move, side) in played {
+            let stats = self.stats.entry((key, book_move)).or_default();
+            stats.games += 1;
+            match result {
+                GameResult::Draw => stats.draws += 1,
+                GameResult::WhiteWins if side == WHITE => stats.wins += 1,
+                GameResult::BlackWins if side != WHITE => stats.wins += 1,
+                _ => (),
+            }
+        }
+        self.games += 1;
+    }
+
+    // Stats for a position and move, mainly for testing
+    pub fn stats(&self, key: u64, book_move: u16) -> Option<MoveStats> {
+        self.stats.get(&(key, book_move)).copied()
+    }
+
+    // Moves played in at least min_games games, with at least
+    // min_score (0 to 1).  Weights are scaled to fit in 16 bits if
+    // necessary, keeping their proportions within each position.
+    pub fn build(&self, min_games: u32, min_score: f64) -> Book {
+        let mut by_key: HashMap<u64, Vec<(u16, u64)>> = HashMap::new();
+        for (&(key, mv), stats) in &self.stats {
+            if stats.games >= min_games && stats.score() >= min_score && stats.weight() > 0 {
+                by_key.entry(key).or_default().push((mv, stats.weight()));
+            }
+        }
+
+        let mut entries = Vec::new();
+        for (key, mut moves) in by_key {
+            // Best moves first, as expected by some programs
+            moves.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            let max = moves[0].1;
+            for (mv, weight) in moves {
+                let weight = if max > u16::MAX as u64 {
+                    std::cmp::max(1, weight * u16::MAX as u64 / max)
+                } else {
+                    weight
+                };
+                entries.push(BookEntry{key: key, mv: mv, weight: weight as u16, learn: 0});
+            }
+        }
+        Book::from_entries(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::PgnReader;
+
+    const PGN: &str = "1. e4 e5 2. Nf3 1-0\n\n1. e4 c5 0-1\n\n1. d4 d5 1/2-1/2\n\n1. e4 e5 *\n\n1. e4 Ke7 1-0\n\n[FEN \"8/8/9/8 w - - 0 1\"]\n\n1. e4 1-0\n";
+
+    #[test]
+    fn build_book() {
+        crate::initialize();
+        let mut builder = BookBuilder::new(2);
+        for game in PgnReader::new(PGN.as_bytes()) {
+            builder.add_game(&game.unwrap());
+        }
+        // The unfinished game, the illegal move and the bad FEN
+        assert_eq!(builder.games(), 3);
+        assert_eq!(builder.skipped(), 3);
+
+        let mut board = Board::from_fen(START_FEN);
+        let start = board.polyglot_key();
+        let e2e4 = board.parse_move("e2e4").unwrap();
+        let d2d4 = board.parse_move("d2d4").unwrap();
+        assert_eq!(builder.stats(start, board.encode_book_move(&e2e4)), Some(MoveStats{games: 2, wins: 1, draws: 0}));
+        assert_eq!(builder.stats(start, board.encode_book_move(&d2d4)), Some(MoveStats{games: 1, wins: 0, draws: 1}));
+
+        // Nf3 is beyond the ply limit, and nothing is kept from the
+        // game with the illegal move
+        assert_eq!(builder.positions(), 5);
+
+        let book = builder.build(1, 0.0);
+        let moves: Vec<(String, u16)> = book.moves(&mut board).iter().map(|(mv, w)| (mv.to_string(), *w)).collect();
+        assert_eq!(moves, vec![("e2e4".to_string(), 2), ("d2d4".to_string(), 1)]);
+
+        board.make_move(&e2e4);
+        board.reset_ply();
+        let moves: Vec<String> = book.moves(&mut board).iter().map(|(mv, _)| mv.to_string()).collect();
+        assert_eq!(moves, vec!["c7c5"]);
+
+        // Filtered by games and score
+        assert_eq!(builder.build(2, 0.0).len(), 1);
+        assert_eq!(builder.build(1, 0.6).len(), 1);
+    }
+}
@@ -0,0 +1,191 @@
+// A game for library use: a board, the position it started from and
+// the moves played since.
+//
+// The protocol loops work on a Board directly.  Game wraps the parts
+// of it that other programs need, so that they don't have to keep
+// track of the search ply or of which moves are legal.
+
+use crate::board::{Board,PositionState,Variant,GameStatus,SearchInfo,SearchResult,START_FEN};
+use crate::moves::Move;
+
+pub struct Game {
+    board: Board,
+    start_fen: String,
+    moves: Vec<Move>,
+}
+
+impl Game {
+    // A standard game from the starting position
+    pub fn new() -> Game {
+        Game::from_fen(START_FEN).expect("FEN error")
+    }
+
+    // None if the FEN can't be parsed
+    pub fn from_fen(fen: &str) -> Option<Game> {
+        Some(Game{
+            board: Board::try_from_fen(fen)?,
+            start_fen: fen.to_string(),
+            moves: Vec::new(),
+        })
+    }
+
+    pub fn with_variant(variant: Variant, fen: &str) -> Option<Game> {
+        let mut game = Game::from_fen(fen)?;
+        game.board.set_variant(variant);
+        Some(game)
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.board.variant()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // The pieces, side to move and other state of the position
+    pub fn state(&self) -> PositionState {
+        self.board.snapshot()
+    }
+
+    pub fn side_to_move(&self) -> usize {
+        self.board.side
+    }
+
+    pub fn fen(&self) -> String {
+        self.board.to_fen()
+    }
+
+    pub fn start_fen(&self) -> &str {
+        &self.start_fen
+    }
+
+    // Moves played since the start position
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let move_list = self.board.generate_all_moves();
+        let mut legal = Vec::new();
+        for smv in move_list.moves.iter() {
+            if self.board.make_move(&smv.mv) {
+                legal.push(smv.mv);
+                self.board.undo_move();
+            }
+        }
+        self.board.reset_ply();
+        legal
+    }
+
+    // A move in coordinate notation (e2e4, e7e8q, P@e4) or SAN (Nf3)
+    pub fn parse_move(&mut self, text: &str) -> Option<Move> {
+        match self.board.parse_move(text) {
+            Some(mv) => Some(mv),
+            None => self.board.parse_san(text),
+        }
+    }
+
+    // Returns false, leaving the game unchanged, if the move is illegal
+    pub fn make_move(&mut self, mv: Move) -> bool {
+        if ! self.legal_moves().contains(&mv) {
+            return false;
+        }
+        self.board.make_move(&mv);
+        self.board.reset_ply();
+        self.moves.push(mv);
+        true
+    }
+
+    // Parse and make a move, returning it if it is legal
+    pub fn play(&mut self, text: &str) -> Option<Move> {
+        let mv = self.parse_move(text)?;
+        if self.make_move(mv) { Some(mv) } else { None }
+    }
+
+    // Take back the last move, returning it
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let mv = self.moves.pop()?;
+        self.board.take_back();
+        Some(mv)
+    }
+
+    pub fn status(&mut self) -> GameStatus {
+        self.board.game_status()
+    }
+
+    // Search the current position with the given limits.  The game is
+    // left unchanged; the best move is not played.
+    pub fn search(&mut self, info: &mut SearchInfo) -> SearchResult {
+        self.board.search(info)
+    }
+
+    pub fn search_depth(&mut self, depth: u32) -> SearchResult {
+        self.search(&mut SearchInfo::new(depth))
+    }
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_and_undo() {
+        let mut game = Game::new();
+        assert_eq!(game.legal_moves().len(), 20);
+        assert!(game.play("e2e4").is_some());
+        assert!(game.play("e5").is_some());
+        assert!(game.play("e1e2").is_some());
+        assert!(game.play("e8e8").is_none());
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2");
+
+        assert_eq!(game.undo_move().map(|mv| mv.to_string()), Some(String::from("e1e2")));
+        assert_eq!(game.fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        assert_eq!(game.start_fen(), START_FEN);
+    }
+
+    #[test]
+    fn status_and_search() {
+        let mut game = Game::new();
+        for mv in &["f3", "e5", "g4"] {
+            assert!(game.play(mv).is_some());
+            assert_eq!(game.status(), GameStatus::Ongoing);
+        }
+        let result = game.search_depth(2);
+        assert_eq!(result.best_move.map(|mv| mv.to_string()), Some(String::from("d8h4")));
+        assert!(game.moves().len() == 3);
+
+        game.play("Qh4#");
+        assert_eq!(game.status(), GameStatus::Checkmate(crate::pieces::BLACK));
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn from_fen() {
+        let mut game = Game::with_variant(Variant::KingOfTheHill, "4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.variant(), Variant::KingOfTheHill);
+        assert_eq!(game.legal_moves().len(), 5);
+        assert!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 x - - 0 1").is_none());
+        assert!(Game::with_variant(Variant::Atomic, "").is_none());
+    }
+
+    #[test]
+    fn search_finished_game() {
+        let mut game = Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(game.status(), GameStatus::Checkmate(crate::pieces::BLACK));
+        assert!(game.search_depth(2).best_move.is_none());
+
+        let mut game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game.status(), GameStatus::Stalemate);
+        let result = game.search_depth(2);
+        assert!(result.best_move.is_none());
+        assert_eq!(result.score, 0);
+    }
+}
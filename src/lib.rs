@@ -2,17 +2,34 @@ pub mod board;
 pub mod xboard;
 pub mod simpleloop;
 pub mod benchmarks;
+pub mod tuner;
+pub mod pgn;
+pub mod bookbuilder;
 
 mod pieces;
 mod bitboard;
 mod moves;
 mod version;
+mod game;
+
+// The types needed to use the engine as a library, so that they can
+// be named without reaching into the modules that define them
+pub use game::Game;
+pub use board::{Board,PositionState,Variant,GameStatus,SearchInfo,SearchResult,Square,START_FEN};
+pub use moves::Move;
+pub use pieces::{Piece,WHITE,BLACK};
+pub use bitboard::Bitboard;
 
 #[macro_use]
 extern crate lazy_static;
 
+// Build the lookup tables up front.  They are built on first use
+// anyway, so calling this is optional; it only keeps the cost out of
+// the first search.
 pub fn initialize() {
     board::init_mvv_lva();
+    board::init_endgames();
+    board::init_kpk();
     bitboard::init_eval_masks();
     bitboard::init_obs_diff_masks();
     pieces::init_move_tables();
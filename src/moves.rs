@@ -1,4 +1,5 @@
 use std::fmt;
+use std::num::NonZeroU32;
 
 use crate::board::Square;
 use crate::pieces::Piece;
@@ -17,69 +18,107 @@ pub enum MoveFlag {
     EnPas,
     PawnStart,
     Castle,
+    // Crazyhouse drop, with the dropped piece kept as promote
+    Drop,
 }
 
+const FLAGS: [MoveFlag; 5] = [MoveFlag::None, MoveFlag::EnPas, MoveFlag::PawnStart, MoveFlag::Castle, MoveFlag::Drop];
+
+// Pieces by their index in a move
+const PIECES: [Piece; 16] = [
+    Piece::WP, Piece::WN, Piece::WB, Piece::WR, Piece::WQ, Piece::WK,
+    Piece::BP, Piece::BN, Piece::BB, Piece::BR, Piece::BQ, Piece::BK,
+    Piece::Empty, Piece::Offboard, Piece::Offboard, Piece::Offboard,
+];
+
+const TO_SHIFT: u32 = 6;
+const CAPTURE_SHIFT: u32 = 12;
+const PROMOTE_SHIFT: u32 = 16;
+const FLAG_SHIFT: u32 = 20;
+
+// A move packed into 32 bits, as in VICE:
+//
+//   bits 0-5    from square
+//   bits 6-11   to square
+//   bits 12-15  captured piece
+//   bits 16-19  promoted piece (or dropped piece)
+//   bits 20-22  flag
+//
+// The captured and promoted pieces can't both be a white pawn (zero),
+// as a pawn is never a promotion, and a dropped piece is never a
+// capture.  So a move is never zero, which lets Option<Move> use
+// zero for None and stay four bytes.
 #[derive(Clone)]
 #[derive(Copy)]
 #[derive(PartialEq)]
-pub struct Move {
-    from: Square,
-    to: Square,
-    pub capture: Piece,
-    pub promote: Piece,
-    flag: MoveFlag,
-}
-
-
+#[derive(Eq)]
+pub struct Move(NonZeroU32);
 
 #[allow(dead_code)]
 impl Move {
     pub fn new(from: Square, to: Square, capture: Piece, promote: Piece, flag: MoveFlag) -> Move {
+        let data = from as u32
+            | (to as u32) << TO_SHIFT
+            | (capture as u32) << CAPTURE_SHIFT
+            | (promote as u32) << PROMOTE_SHIFT
+            | (flag as u32) << FLAG_SHIFT;
+        Move(NonZeroU32::new(data).expect("move encodes as zero"))
+    }
 
-        Move {
-            from: from,
-            to: to,
-            capture: capture,
-            promote: promote,
-            flag: flag,
-        }
+    // Drop a piece from the pocket in Crazyhouse.  The from square is
+    // the same as the to square.
+    pub fn new_drop(piece: Piece, to: Square) -> Move {
+        Move::new(to, to, Piece::Empty, piece, MoveFlag::Drop)
     }
 
     pub fn from(&self) -> Square {
-        self.from
+        (self.0.get() & 0x3f) as Square
     }
 
     pub fn to(&self) -> Square {
-        self.to
+        ((self.0.get() >> TO_SHIFT) & 0x3f) as Square
+    }
+
+    pub fn capture(&self) -> Piece {
+        PIECES[((self.0.get() >> CAPTURE_SHIFT) & 0xf) as usize]
+    }
+
+    // The promoted piece, which is also the dropped piece for a drop
+    pub fn promote(&self) -> Piece {
+        PIECES[((self.0.get() >> PROMOTE_SHIFT) & 0xf) as usize]
+    }
+
+    fn flag(&self) -> MoveFlag {
+        FLAGS[(self.0.get() >> FLAG_SHIFT) as usize]
     }
 
     pub fn is_capture(&self) -> bool {
-        self.capture.exists()
+        self.capture().exists()
     }
 
     pub fn is_promotion(&self) -> bool {
-        self.promote.exists()
+        self.promote().exists() && ! self.is_drop()
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.flag() == MoveFlag::Drop
+    }
+
+    // The piece dropped, or Empty for other moves
+    pub fn dropped(&self) -> Piece {
+        if self.is_drop() { self.promote() } else { Piece::Empty }
     }
 
     pub fn is_en_pas(&self) -> bool {
-        match self.flag {
-            MoveFlag::EnPas => true,
-            _ => false,
-        }
+        self.flag() == MoveFlag::EnPas
     }
 
     pub fn is_castle(&self) -> bool {
-        match self.flag {
-            MoveFlag::Castle => true,
-            _ => false,
-        }
+        self.flag() == MoveFlag::Castle
     }
 
     pub fn is_pawn_start(&self) -> bool {
-        match self.flag {
-            MoveFlag::PawnStart => true,
-            _ => false,
-        }
+        self.flag() == MoveFlag::PawnStart
     }
 }
 
@@ -88,17 +127,27 @@ impl fmt::Display for Move {
         let from = self.from();
         let to = self.to();
 
+        if self.is_drop() {
+            // P@e4, with the piece in upper case for either side
+            return write!(f, "{}@{}", self.promote().to_string().to_ascii_uppercase(), square_string(to));
+        }
+
         write!(f, "{}{}", square_string(from), square_string(to))?;
 
-        if self.promote.exists() {
+        let promote = self.promote();
+        if promote.exists() {
             let mut pchar = 'q';
-            if self.promote.is_knight() {
+            if promote.is_knight() {
                 pchar = 'n'
             }
-            else if self.promote.is_rook_or_queen() && ! self.promote.is_bishop_or_queen() {
+            else if promote.is_king() {
+                // Only in antichess
+                pchar = 'k';
+            }
+            else if promote.is_rook_or_queen() && ! promote.is_bishop_or_queen() {
                 pchar = 'r';
             }
-            else if self.promote.is_bishop_or_queen() && ! self.promote.is_rook_or_queen() {
+            else if promote.is_bishop_or_queen() && ! promote.is_rook_or_queen() {
                 pchar = 'b';
             }
             write!(f, "{}", pchar)?;
@@ -117,5 +166,57 @@ mod tests {
     fn move_string() {
         let mv = Move::new(board::Position::C1 as Square, board::Position::C3 as Square, Piece::Empty, Piece::WR, MoveFlag::None);
         assert_eq!(mv.to_string(), "c1c3r");
+
+        let mv = Move::new_drop(Piece::BN, board::Position::F6 as Square);
+        assert!(mv.is_drop() && ! mv.is_promotion());
+        assert_eq!(mv.to_string(), "N@f6");
+    }
+
+    #[test]
+    fn packing() {
+        let mv = Move::new(board::Position::H7 as Square, board::Position::G8 as Square, Piece::BN, Piece::WQ, MoveFlag::None);
+        assert_eq!(mv.from(), board::Position::H7 as Square);
+        assert_eq!(mv.to(), board::Position::G8 as Square);
+        assert_eq!(mv.capture(), Piece::BN);
+        assert_eq!(mv.promote(), Piece::WQ);
+        assert!(mv.is_capture() && mv.is_promotion() && ! mv.is_castle());
+
+        // Capturing a white pawn, without a promotion
+        let mv = Move::new(board::Position::A1 as Square, board::Position::A1 as Square, Piece::WP, Piece::Empty, MoveFlag::EnPas);
+        assert!(mv.is_en_pas() && mv.capture() == Piece::WP && ! mv.is_promotion());
+
+        assert_eq!(std::mem::size_of::<Move>(), 4);
+        assert_eq!(std::mem::size_of::<Option<Move>>(), 4);
+    }
+
+    // Every flag, captured piece and promoted piece comes back out of
+    // the bit layout unchanged, with the squares at both ends
+    #[test]
+    fn packing_round_trip() {
+        for &flag in FLAGS.iter() {
+            for &capture in PIECES[..13].iter() {
+                for &promote in PIECES[..13].iter() {
+                    // Pawns are only dropped, and drops capture nothing
+                    let is_drop = flag == MoveFlag::Drop;
+                    if (promote.is_pawn() && ! is_drop) || (is_drop && (capture.exists() || ! promote.exists())) {
+                        continue;
+                    }
+                    for &(from, to) in &[(0, 63), (63, 0), (12, 28)] {
+                        let mv = Move::new(from, to, capture, promote, flag);
+                        assert_eq!(mv.from(), from);
+                        assert_eq!(mv.to(), to);
+                        assert_eq!(mv.capture(), capture);
+                        assert_eq!(mv.promote(), promote);
+                        assert!(mv.flag() == flag);
+                        assert_eq!(mv.is_capture(), capture.exists());
+                        assert_eq!(mv.is_promotion(), promote.exists() && ! is_drop);
+                        assert_eq!(mv.is_drop(), is_drop);
+                        assert_eq!(mv.is_en_pas(), flag == MoveFlag::EnPas);
+                        assert_eq!(mv.is_pawn_start(), flag == MoveFlag::PawnStart);
+                        assert_eq!(mv.is_castle(), flag == MoveFlag::Castle);
+                    }
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,239 @@
+// Streaming PGN reader.
+//
+// Games are read one at a time, a line at a time, so that collections
+// of any size can be processed.  Only the information needed for
+// building opening books is kept: the tags, the main line moves (in
+// SAN) and the result.  Comments, variations and numeric annotation
+// glyphs are skipped.  Input that is not valid UTF-8 (older
+// collections are often Latin-1) is converted lossily.
+
+use std::io::{self, BufRead};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    fn parse(s: &str) -> Option<GameResult> {
+        match s {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+}
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    // None for unfinished games ("*")
+    pub result: Option<GameResult>,
+}
+
+impl PgnGame {
+    fn new() -> PgnGame {
+        PgnGame{tags: Vec::new(), moves: Vec::new(), result: None}
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    buffer: Vec<u8>,
+    // A tag line that started the next game
+    pending: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> PgnReader<R> {
+        PgnReader{reader: reader, buffer: Vec::new(), pending: None}
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+        self.buffer.clear();
+        if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&self.buffer).into_owned()))
+    }
+
+    // Returns None at the end of the input
+    pub fn read_game(&mut self) -> io::Result<Option<PgnGame>> {
+        let mut game = PgnGame::new();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        let mut variation_depth = 0;
+
+        while let Some(line) = self.read_line()? {
+            let mut line = line.trim();
+
+            if in_comment {
+                match line.find('}') {
+                    Some(i) => {
+                        in_comment = false;
+                        line = &line[i+1..];
+                    }
+                    None => continue,
+                }
+            } else if line.starts_with('[') && variation_depth == 0 {
+                if in_movetext {
+                    // A game without a result
+                    self.pending = Some(line.to_string());
+                    return Ok(Some(game));
+                }
+                if let Some(tag) = parse_tag(line) {
+                    game.tags.push(tag);
+                }
+                continue;
+            } else if line.starts_with('%') {
+                // Escape mechanism
+                continue;
+            }
+
+            let mut chars = line.char_indices().peekable();
+            while let Some((start, c)) = chars.next() {
+                match c {
+                    '{' => {
+                        match line[start..].find('}') {
+                            Some(end) => {
+                                while chars.peek().is_some_and(|&(i, _)| i <= start + end) {
+                                    chars.next();
+                                }
+                            }
+                            None => {
+                                in_comment = true;
+                                break;
+                            }
+                        }
+                    }
+                    // Comment to the end of the line
+                    ';' => break,
+                    '(' => variation_depth += 1,
+                    ')' => variation_depth -= 1,
+                    c if c.is_whitespace() => (),
+                    _ => {
+                        let mut end = line.len();
+                        while let Some(&(i, c)) = chars.peek() {
+                            if c.is_whitespace() || "{}();".contains(c) {
+                                end = i;
+                                break;
+                            }
+                            chars.next();
+                        }
+                        in_movetext = true;
+                        if variation_depth > 0 {
+                            continue;
+                        }
+                        let token = &line[start..end];
+                        if token == "*" {
+                            return Ok(Some(game));
+                        }
+                        if let Some(result) = GameResult::parse(token) {
+                            game.result = Some(result);
+                            return Ok(Some(game));
+                        }
+                        if let Some(san) = move_text(token) {
+                            game.moves.push(san.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        if in_movetext || ! game.tags.is_empty() {
+            Ok(Some(game))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = io::Result<PgnGame>;
+
+    fn next(&mut self) -> Option<io::Result<PgnGame>> {
+        self.read_game().transpose()
+    }
+}
+
+// [Name "Value"]
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.trim_end().strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+// The move in a token, without a move number such as "12." or
+// "12...".  Returns None for move numbers and annotation glyphs.
+fn move_text(token: &str) -> Option<&str> {
+    if token.starts_with('$') {
+        return None;
+    }
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches('.');
+    if san.is_empty() {
+        None
+    } else if san.len() < token.len() && ! token[..token.len() - san.len()].contains('.') {
+        // Digits without a period, not a move number
+        Some(token)
+    } else {
+        Some(san)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Test"]
+[White "A \"quoted\" name"]
+[Result "1-0"]
+
+1. e4 e5 2.Nf3 {A comment
+spanning lines} Nc6 3. Bb5 $1 (3. Bc4 Bc5 (3... Nf6)) 3... a6 ; rest of line
+4. Ba4 1-0
+
+[Event "Second"]
+
+1. d4 d5 *
+[Event "No result"]
+
+1. c4
+"#;
+
+    #[test]
+    fn read_games() {
+        let games: Vec<PgnGame> = PgnReader::new(PGN.as_bytes()).collect::<io::Result<_>>().unwrap();
+        assert_eq!(games.len(), 3);
+
+        assert_eq!(games[0].tag("Event"), Some("Test"));
+        assert_eq!(games[0].tag("White"), Some("A \"quoted\" name"));
+        assert_eq!(games[0].moves, vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]);
+        assert_eq!(games[0].result, Some(GameResult::WhiteWins));
+
+        assert_eq!(games[1].moves, vec!["d4", "d5"]);
+        assert_eq!(games[1].result, None);
+
+        assert_eq!(games[2].tag("Event"), Some("No result"));
+        assert_eq!(games[2].moves, vec!["c4"]);
+    }
+
+    #[test]
+    fn move_numbers() {
+        assert_eq!(move_text("12."), None);
+        assert_eq!(move_text("12..."), None);
+        assert_eq!(move_text("12.Nf3"), Some("Nf3"));
+        assert_eq!(move_text("e4"), Some("e4"));
+        assert_eq!(move_text("$14"), None);
+    }
+}
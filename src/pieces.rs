@@ -143,6 +143,11 @@ impl Piece {
         }
     }
 
+    // From a FEN character
+    pub fn from_char(c: char) -> Option<Piece> {
+        PIECE_TYPES.iter().copied().find(|piece| piece.to_string().starts_with(c))
+    }
+
     pub fn swap(&self) -> Piece {
         match *self {
             Piece::WP => Piece::BP,
@@ -1,6 +1,6 @@
 use std::io::{self, Write};
 
-use crate::board::{Board,START_FEN,SearchInfo,GameMode};
+use crate::board::{Board,START_FEN,SearchInfo,ConsoleObserver};
 
 // A simple io loop for text based moves and searches
 pub fn simple_loop() {
@@ -19,11 +19,17 @@ pub fn simple_loop() {
         let mut input = String::new();
         io::stdin().read_line(&mut input).unwrap();
 
+        if input.starts_with("eval") {
+            println!("{}", board.evaluate_breakdown());
+            continue;
+        }
+
         match input.chars().next().unwrap() {
             'q' => { break; }
             't' => { board.undo_move(); }
             's' => {
-                let mut info = SearchInfo::new(6, GameMode::Console); 
+                let mut info = SearchInfo::new(6);
+                info.set_observer(Box::new(ConsoleObserver{show_thinking: true}));
                 board.search(&mut info);
             }
             _ => {
@@ -31,6 +37,10 @@ pub fn simple_loop() {
                     Some(mv) => {
                         board.store_pv_move(mv);
                         board.make_move(&mv);
+                        let status = board.game_status();
+                        if status.is_over() {
+                            println!("{}", status);
+                        }
                     }
                     _ => { println!("Move not parsed"); }
                 }
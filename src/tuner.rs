@@ -0,0 +1,290 @@
+// Texel-style tuning of the evaluation weights.
+//
+// Each training position is resolved to a quiet position using the
+// quiescence search, and the evaluation of that position is recorded
+// as a linear combination of the evaluation weights (the evaluation
+// is linear in the weights once the position is fixed).  The weights
+// are then fit by minimizing the mean squared error between the game
+// result and a logistic function of the evaluation.
+//
+// See: https://www.chessprogramming.org/Texel%27s_Tuning_Method
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::board::{Board,SearchInfo,EvalParams,EvalTerm,EvalTrace,MAX_DEPTH,DEFAULT_EVAL_PARAMS};
+use crate::pieces::WHITE;
+
+// Collects the coefficient of each flattened weight, from white's
+// point of view
+struct Coefficients(Vec<i32>);
+
+impl EvalTrace for Coefficients {
+    fn add(&mut self, term: EvalTerm, index: usize, side: usize, count: i32, _weight: i32) {
+        let i = EvalParams::offset(term) + index;
+        if side == WHITE {
+            self.0[i] += count;
+        } else {
+            self.0[i] -= count;
+        }
+    }
+}
+
+pub struct TuningPosition {
+    // Non-zero (index, coefficient) pairs
+    coefficients: Vec<(usize, i32)>,
+    // Game result from white's point of view: 1, 0.5 or 0
+    result: f64,
+}
+
+impl TuningPosition {
+    // Resolve the position with a quiescence search and record the
+    // evaluation coefficients of the resulting quiet position.  None
+    // if the FEN can't be parsed.
+    pub fn new(fen: &str, result: f64) -> Option<TuningPosition> {
+        let board = quiet_board(fen)?;
+
+        let params = EvalParams::from_slice(&vec![0; num_weights()]);
+        let mut coefficients = Coefficients(vec![0; num_weights()]);
+        board.evaluate_with(&params, &mut coefficients);
+
+        Some(TuningPosition {
+            coefficients: coefficients.0.into_iter().enumerate().filter(|&(_, c)| c != 0).collect(),
+            result: result,
+        })
+    }
+
+    // Evaluation from white's point of view
+    fn evaluate(&self, weights: &[f64]) -> f64 {
+        self.coefficients.iter().map(|&(i, c)| c as f64 * weights[i]).sum()
+    }
+}
+
+// The position at the end of the quiescence search's principal
+// variation
+fn quiet_board(fen: &str) -> Option<Board> {
+    let mut board = Board::try_from_fen(fen)?;
+    let mut info = SearchInfo::new(0);
+
+    board.clear_for_search(&mut info);
+    board.quiescence(i32::MIN + 1, i32::MAX, &mut info);
+    board.get_pv_line(MAX_DEPTH);
+    for mv in board.pv_array.clone() {
+        board.make_move(&mv);
+    }
+    Some(board)
+}
+
+fn num_weights() -> usize {
+    DEFAULT_EVAL_PARAMS.to_vec().len()
+}
+
+// Expected score for white given an evaluation in centipawns
+pub fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+// Parse a line containing a FEN string followed by the game result,
+// either as "1-0", "0-1", "1/2-1/2" or as "1.0", "0.5", "0.0".  The
+// result may be wrapped in quotes or brackets, as produced by common
+// extraction tools.
+pub fn parse_line(line: &str) -> Option<(&str, f64)> {
+    for (i, word) in line.split_whitespace().enumerate() {
+        // Skip the board, side and castling fields, which may
+        // otherwise contain digits
+        if i < 3 {
+            continue;
+        }
+        let trimmed = word.trim_matches(|c| c == '"' || c == '[' || c == ']' || c == ';' || c == ',');
+        let result = match trimmed {
+            "1-0" | "1.0" => 1.0,
+            "0-1" | "0.0" => 0.0,
+            "1/2-1/2" | "0.5" => 0.5,
+            _ => continue,
+        };
+        return Some((line, result));
+    }
+    None
+}
+
+// The positions, and the number of lines skipped because they have no
+// result or the FEN can't be parsed.  Blank lines are ignored.
+pub fn load_positions(path: &str) -> io::Result<(Vec<TuningPosition>, usize)> {
+    let f = BufReader::new(File::open(path)?);
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for line in f.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(&line).and_then(|(fen, result)| TuningPosition::new(fen, result)) {
+            Some(position) => positions.push(position),
+            None => skipped += 1,
+        }
+    }
+    Ok((positions, skipped))
+}
+
+pub struct Tuner {
+    positions: Vec<TuningPosition>,
+    weights: Vec<f64>,
+    pub k: f64,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TuningPosition>, params: &EvalParams) -> Tuner {
+        Tuner {
+            positions: positions,
+            weights: params.to_vec().into_iter().map(|w| w as f64).collect(),
+            k: 1.0,
+        }
+    }
+
+    pub fn params(&self) -> EvalParams {
+        let values: Vec<i32> = self.weights.iter().map(|w| w.round() as i32).collect();
+        EvalParams::from_slice(&values)
+    }
+
+    pub fn mean_error(&self, k: f64) -> f64 {
+        let sum: f64 = self.positions.iter().map(|p| {
+            let err = p.result - sigmoid(p.evaluate(&self.weights), k);
+            err * err
+        }).sum();
+        sum / self.positions.len() as f64
+    }
+
+    // Find the scaling constant that best fits the current weights,
+    // by successively refining a grid search
+    pub fn fit_k(&mut self) -> f64 {
+        let mut best = self.k;
+        let mut best_error = self.mean_error(best);
+        let mut step = 0.1;
+        let (mut lo, mut hi) = (0.0, 3.0);
+        for _ in 0..4 {
+            let mut k = lo;
+            while k <= hi {
+                let error = self.mean_error(k);
+                if error < best_error {
+                    best = k;
+                    best_error = error;
+                }
+                k += step;
+            }
+            lo = f64::max(0.0, best - step);
+            hi = best + step;
+            step /= 10.0;
+        }
+        self.k = best;
+        best
+    }
+
+    fn gradient(&self) -> Vec<f64> {
+        let mut grad = vec![0.0; self.weights.len()];
+        let scale = self.k * std::f64::consts::LN_10 / 400.0;
+        for p in &self.positions {
+            let s = sigmoid(p.evaluate(&self.weights), self.k);
+            let term = -2.0 * (p.result - s) * s * (1.0 - s) * scale;
+            for &(i, c) in &p.coefficients {
+                grad[i] += term * c as f64;
+            }
+        }
+        let n = self.positions.len() as f64;
+        grad.iter_mut().for_each(|g| *g /= n);
+        grad
+    }
+
+    // Minimize the error with gradient descent, using Adam step sizes
+    // so that the learning rate is roughly in centipawns.  Returns
+    // the final error.
+    pub fn optimize(&mut self, epochs: u32, rate: f64, verbose: bool) -> f64 {
+        const BETA1: f64 = 0.9;
+        const BETA2: f64 = 0.999;
+        const EPSILON: f64 = 1e-8;
+
+        let mut m = vec![0.0; self.weights.len()];
+        let mut v = vec![0.0; self.weights.len()];
+
+        for epoch in 1..=epochs {
+            let grad = self.gradient();
+            for i in 0..self.weights.len() {
+                m[i] = BETA1 * m[i] + (1.0 - BETA1) * grad[i];
+                v[i] = BETA2 * v[i] + (1.0 - BETA2) * grad[i] * grad[i];
+                let m_hat = m[i] / (1.0 - BETA1.powi(epoch as i32));
+                let v_hat = v[i] / (1.0 - BETA2.powi(epoch as i32));
+                self.weights[i] -= rate * m_hat / (v_hat.sqrt() + EPSILON);
+            }
+            if verbose && epoch % 100 == 0 {
+                println!("epoch {} error {:.6}", epoch, self.mean_error(self.k));
+            }
+        }
+        self.mean_error(self.k)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{NoTrace,START_FEN};
+
+    #[test]
+    fn parse_results() {
+        let fen = "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1";
+        assert_eq!(parse_line(&format!("{} \"1-0\";", fen)).unwrap().1, 1.0);
+        assert_eq!(parse_line(&format!("{} [0.5]", fen)).unwrap().1, 0.5);
+        assert_eq!(parse_line(&format!("{} 0-1", fen)).unwrap().1, 0.0);
+        assert!(parse_line(fen).is_none());
+    }
+
+    // The coefficients must reproduce the evaluation, otherwise the
+    // tuner would be fitting a different function
+    #[test]
+    fn coefficients_match_evaluate() {
+        let fen = "2k1r2r/Bpq3pp/3b4/3Bp3/8/7b/PPP1QP2/R3R1K1 w - - 0 1";
+        let pos = TuningPosition::new(fen, 0.5).unwrap();
+        let weights: Vec<f64> = DEFAULT_EVAL_PARAMS.to_vec().into_iter().map(|w| w as f64).collect();
+        // Already quiet, so no moves are made
+        assert_eq!(pos.evaluate(&weights) as i32, Board::from_fen(fen).evaluate());
+
+        // The quiescence search takes the hanging rook, and the
+        // coefficients are those of the position after the capture,
+        // with black to move
+        let fen = "r3k3/ppp2ppp/8/3r4/8/8/PPPQ1PPP/4K3 w - - 0 1";
+        let pos = TuningPosition::new(fen, 1.0).unwrap();
+        let board = quiet_board(fen).unwrap();
+        assert_eq!(board.to_fen(), "r3k3/ppp2ppp/8/3Q4/8/8/PPP2PPP/4K3 b - - 0 1");
+        assert_eq!(pos.evaluate(&weights) as i32, - board.evaluate_with(&DEFAULT_EVAL_PARAMS, &mut NoTrace));
+    }
+
+    #[test]
+    fn load_positions_skips_bad_lines() {
+        let path = std::env::temp_dir().join("chareth_tuner_positions.txt");
+        std::fs::write(&path, format!("{} 1-0\n\n8/8/9/8 w - - 0 1 0-1\n{}\n{} [0.5]\n", START_FEN, START_FEN, START_FEN)).unwrap();
+        let (positions, skipped) = load_positions(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(skipped, 2);
+    }
+
+    #[test]
+    fn flatten_params() {
+        let values = DEFAULT_EVAL_PARAMS.to_vec();
+        assert_eq!(values.len(), num_weights());
+        assert_eq!(EvalParams::from_slice(&values).to_vec(), values);
+    }
+
+    #[test]
+    fn optimize_reduces_error() {
+        let positions = vec![
+            TuningPosition::new(START_FEN, 0.5).unwrap(),
+            TuningPosition::new("4k3/8/8/8/8/8/4P3/3QK3 w - - 0 1", 1.0).unwrap(),
+            TuningPosition::new("3qk3/4p3/8/8/8/8/8/4K3 w - - 0 1", 0.0).unwrap(),
+        ];
+        let mut tuner = Tuner::new(positions, &DEFAULT_EVAL_PARAMS);
+        tuner.fit_k();
+        let before = tuner.mean_error(tuner.k);
+        let after = tuner.optimize(20, 1.0, false);
+        assert!(after < before);
+    }
+}
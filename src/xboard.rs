@@ -1,11 +1,214 @@
 use std::thread;
-use std::sync::mpsc;
+use std::sync::mpsc::{self,RecvTimeoutError};
 use std::io::{self, Write};
+use std::time::Duration;
 
-use crate::board::{Board,SearchInfo,GameMode,MAX_DEPTH,START_FEN};
-use crate::pieces::{BLACK,BOTH};
+use crate::board::{Board,SearchInfo,SearchHandle,XboardObserver,NodeClock,Adjudicator,Decision,BookOptions,BookSelection,CastleNotation,Variant,MAX_DEPTH,START_FEN};
+use crate::board::{fr_to_sq,FILE_A,FILE_H,RANK_1,RANK_8};
+use crate::pieces::{WHITE,BLACK,BOTH};
 use crate::version::PROGRAM_NAME;
 
+// Moves left until the next time control for each side
+struct TimeControl {
+    moves_to_go: [u32; 2],
+    // xboard uses 0 to indicate that whole game is played in one period
+    moves_per_session: u32,
+}
+
+impl TimeControl {
+    fn count_move(&mut self, side: usize) {
+        if self.moves_per_session != 0 {
+            self.moves_to_go[side] -= 1;
+            if self.moves_to_go[side] < 1 {
+                self.moves_to_go[side] = self.moves_per_session;
+            }
+        }
+    }
+}
+
+// Wait for the engine's search to finish, and play its move, unless
+// it resigns.  Returns true if the game is over.
+fn finish_search(search: &mut Option<SearchHandle>, board: &mut Board, time_control: &mut TimeControl,
+                 adjudicator: &mut Adjudicator, notation: CastleNotation, claimed: &mut Option<String>) -> bool {
+    let result = match search.take() {
+        Some(handle) => handle.join(),
+        None => return false,
+    };
+    let mv = match result.best_move {
+        Some(mv) => mv,
+        None => return false,
+    };
+
+    match adjudicator.update(result.score, board.move_number()) {
+        Decision::Resign => {
+            println!("resign");
+            return true;
+        }
+        Decision::OfferDraw => println!("offer draw"),
+        Decision::Play => (),
+    }
+
+    println!("move {}", board.move_format(notation).format(&mv));
+    board.make_move(&mv);
+    board.reset_ply();
+    time_control.count_move(board.side^1);
+
+    // Claim a draw or mate made by the move
+    claim_result(board, claimed)
+}
+
+// Print the result if the game is over, and return whether it is.
+// This is checked after every command, so the result is only printed
+// the first time for each position; claimed holds its FEN.
+fn claim_result(board: &mut Board, claimed: &mut Option<String>) -> bool {
+    let status = board.game_status();
+    if status.is_over() {
+        let fen = board.to_fen();
+        if claimed.as_deref() != Some(fen.as_str()) {
+            println!("{}", status);
+            *claimed = Some(fen);
+        }
+    }
+    status.is_over()
+}
+
+// Seconds, possibly fractional, to milliseconds
+fn parse_seconds(s: &str) -> Option<u64> {
+    let seconds: f64 = s.parse().ok()?;
+    if seconds >= 0.0 { Some((seconds * 1000.0) as u64) } else { None }
+}
+
+// level MPS BASE INC
+//
+// BASE is in minutes, or minutes:seconds, and INC is in seconds.
+// Returns the moves per session, and the base time and increment in
+// milliseconds.
+fn parse_level(args: &[&str]) -> Option<(u32, u64, u64)> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves_per_session = args[0].parse().ok()?;
+
+    let mut base_args = args[1].split(':');
+    let mut base = base_args.next()?.parse::<u64>().ok()? * 60000;
+    if let Some(sec) = base_args.next() {
+        base += sec.parse::<u64>().ok()? * 1000;
+    }
+
+    Some((moves_per_session, base, parse_seconds(args[2])?))
+}
+
+// Board set up with the edit command
+struct Editor {
+    // Indexed by rank * 8 + file
+    pieces: [Option<char>; 64],
+    // Color of the pieces being placed
+    color: usize,
+    side: usize,
+}
+
+impl Editor {
+    fn new(board: &Board) -> Editor {
+        let mut pieces = [None; 64];
+        for rank in RANK_1..=RANK_8 {
+            for file in FILE_A..=FILE_H {
+                let piece = board.pieces[fr_to_sq(file, rank) as usize];
+                if piece.exists() {
+                    pieces[(rank * 8 + file) as usize] = piece.to_string().chars().next();
+                }
+            }
+        }
+        Editor{pieces: pieces, color: WHITE, side: board.side}
+    }
+
+    // c (change color), # (clear board), Pa4 (place a piece), xa4
+    // (clear a square).  Returns false if not understood.
+    fn command(&mut self, cmd: &str) -> bool {
+        match cmd {
+            "c" => {
+                self.color ^= 1;
+                return true;
+            }
+            "#" => {
+                self.pieces = [None; 64];
+                return true;
+            }
+            _ => (),
+        }
+
+        let chars: Vec<char> = cmd.chars().collect();
+        if chars.len() != 3 || ! ('a'..='h').contains(&chars[1]) || ! ('1'..='8').contains(&chars[2]) {
+            return false;
+        }
+        let sq = (chars[2] as usize - '1' as usize) * 8 + (chars[1] as usize - 'a' as usize);
+        let piece = chars[0].to_ascii_uppercase();
+        match piece {
+            'X' => self.pieces[sq] = None,
+            'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                self.pieces[sq] = Some(if self.color == WHITE { piece } else { piece.to_ascii_lowercase() });
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Castling is allowed if the king and rook are on their original
+    // squares
+    fn to_fen(&self) -> String {
+        let mut placement = Vec::new();
+        for rank in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.pieces[rank * 8 + file] {
+                    Some(c) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            placement.push(row);
+        }
+
+        let mut castling = String::new();
+        for &(king, rook, c) in &[(4, 7, 'K'), (4, 0, 'Q'), (60, 63, 'k'), (60, 56, 'q')] {
+            let (king_piece, rook_piece) = if c.is_uppercase() { ('K', 'R') } else { ('k', 'r') };
+            if self.pieces[king] == Some(king_piece) && self.pieces[rook] == Some(rook_piece) {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        format!("{} {} {} - 0 1", placement.join("/"), if self.side == WHITE { 'w' } else { 'b' }, castling)
+    }
+}
+
+// Set the side to move, as with the (protocol version 1) white and
+// black commands
+fn set_side(board: Board, side: usize) -> Board {
+    if board.side == side {
+        return board;
+    }
+    let fen = board.to_fen();
+    let fields: Vec<&str> = fen.split(' ').collect();
+    let side_char = if side == WHITE { "w" } else { "b" };
+    let fen = format!("{} {} {} - 0 1", fields[0], side_char, fields[2]);
+    board.update_from_fen(&fen)
+}
+
+fn error(reason: &str, command: &str) {
+    println!("Error ({}): {}", reason, command);
+}
+
 pub fn xboard_loop() {
 
     let (tx, rx) = mpsc::channel();
@@ -13,169 +216,436 @@ pub fn xboard_loop() {
     thread::spawn(move || {
         loop {
             let mut buffer = String::new();
-            io::stdin().read_line(&mut buffer).unwrap();
-            tx.send(buffer).unwrap();
+            // The GUI has gone away if stdin is closed
+            if io::stdin().read_line(&mut buffer).unwrap_or(0) == 0 {
+                buffer = String::from("quit\n");
+            }
+            if tx.send(buffer).is_err() {
+                break;
+            }
         }
     });
 
     let mut board = Board::new();
     board.side = BLACK;
-    let mut info = SearchInfo::new(5, GameMode::Xboard);
-    // Store a receiver in the search info so that it can catch "stop"
-    // messages
-    info.set_receiver(&rx);
 
     let mut depth = MAX_DEPTH; // Default max depth
-    let mut moves_to_go: [u32; 2] = [30, 30];
+    let mut time_control = TimeControl{moves_to_go: [30, 30], moves_per_session: 0};
     let mut move_time: Option<u64> = None;
     let mut time: Option<u64> = None;
     let mut inc: Option<u64> = None;
-    
+    // Nodes per second of "time", set by the nps command
+    let mut nps: Option<u64> = None;
+    let mut show_thinking = true;
+
     let mut engine_side = BOTH;
-    let mut time_left: u64;
-    // xboard uses 0 to indicate that whole game is played in one period
-    let mut moves_per_session = 0;
+    let mut adjudicator = Adjudicator::new();
+    let mut book_options = BookOptions::new();
+    // Castling is sent as O-O and O-O-O in Chess960
+    // (variant fischerandom)
+    let mut castle_notation = CastleNotation::KingMove;
+
+    // The engine's search runs in the background so that commands
+    // are still read while thinking
+    let mut search: Option<SearchHandle> = None;
+    // The position whose result was last claimed
+    let mut claimed: Option<String> = None;
+
+    // Analyze mode searches the current position until it changes
+    let mut analyzing = false;
+    let mut analysis: Option<SearchHandle> = None;
+
+    // Set while in edit mode
+    let mut editor: Option<Editor> = None;
 
     loop {
         io::stdout().flush().unwrap();
 
-        if board.side == engine_side && ! board.check_game_result() {
-            info.set_depth(depth);
+        if search.as_ref().is_some_and(|handle| handle.is_finished())
+            && finish_search(&mut search, &mut board, &mut time_control, &mut adjudicator, castle_notation, &mut claimed) {
+            engine_side = BOTH;
+        }
+
+        if search.is_none() && board.side == engine_side && ! claim_result(&mut board, &mut claimed) {
+            let move_format = board.move_format(castle_notation);
+            if let Some(mv) = book_options.probe(&mut board) {
+                println!("move {}", move_format.format(&mv));
+                board.make_move(&mv);
+                board.reset_ply();
+                time_control.count_move(board.side^1);
+                continue;
+            }
+
+            let mut info = SearchInfo::new(depth);
+            info.set_observer(Box::new(XboardObserver{show_thinking: show_thinking, move_format: move_format}));
+            if let Some(n) = nps {
+                info.set_clock(Box::new(NodeClock::new(Duration::from_nanos(1_000_000_000 / n))));
+            }
             // Todo: is there a case with only depth and no time limit
             // that needs to be handled?
-            info.set_search_time(time, move_time, moves_to_go[board.side], inc);
+            info.set_search_time(time, move_time, time_control.moves_to_go[board.side], inc);
 
-            println!("time:{:?} depth:{} mvoestogo:{:?} mps:{}", time, depth, moves_to_go, moves_per_session);
-            // Unlike vice, we have search return the move and make it
-            // here, for clarity
-            let best_move = board.search(&mut info);
-            if let Some(mv) = best_move {
-                board.make_move(&mv);
+            println!("# search: time:{:?} depth:{} moves_to_go:{:?} mps:{}", time, depth, time_control.moves_to_go, time_control.moves_per_session);
+            search = Some(SearchHandle::start(board.clone(), info));
+        }
+
+        if analyzing && analysis.is_none() {
+            let mut info = SearchInfo::new(MAX_DEPTH);
+            info.set_observer(Box::new(XboardObserver{show_thinking: true, move_format: board.move_format(castle_notation)}));
+            analysis = Some(SearchHandle::start(board.clone(), info));
+        }
+
+        io::stdout().flush().unwrap();
+
+        // While searching, check regularly whether the search has
+        // finished
+        let input = if search.is_some() {
+            match rx.recv_timeout(Duration::from_millis(5)) {
+                Ok(input) => input,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => panic!("stdin channel closed"),
+            }
+        } else {
+            match rx.recv() {
+                Ok(input) => input,
+                _ => panic!("stdin channel closed"),
             }
+        };
 
-            if moves_per_session != 0 {
-                moves_to_go[board.side^1] -= 1;
-                if moves_to_go[board.side^1] < 1 {
-                    moves_to_go[board.side^1] = moves_per_session;
+        let mut words = input.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        // Edit mode has its own commands, ending with "."
+        if let Some(ed) = &mut editor {
+            if command == "." {
+                board = board.update_from_fen(&ed.to_fen());
+                editor = None;
+                if let Some(handle) = analysis.take() {
+                    handle.stop_and_join();
                 }
+            } else if ! ed.command(command) {
+                error("invalid edit command", input.trim());
             }
+            continue;
+        }
 
-            io::stdout().flush().unwrap();
-
-            if info.quit {
+        // Commands that are handled while searching
+        match command {
+            "ping" => {
+                if let Some(n) = args.first() {
+                    println!("pong {}", n);
+                }
+                continue;
+            }
+            "?" => {
+                // Move now
+                if let Some(handle) = &search {
+                    handle.stop();
+                }
+                continue;
+            }
+            "." => {
+                // Analysis status: time (centiseconds), nodes, depth,
+                // moves left and total moves at the root, current move
+                if let Some(handle) = &analysis {
+                    let progress = handle.progress();
+                    let current = progress.current_move().map_or(String::new(), |mv| board.move_format(castle_notation).format(&mv));
+                    println!("stat01: {} {} {} {} {} {}", handle.elapsed().as_millis() / 10, progress.nodes(),
+                             progress.depth(), progress.moves_left(), progress.moves_total(), current);
+                }
+                continue;
+            }
+            "post" | "nopost" => {
+                // Applies from the next search
+                show_thinking = command == "post";
+                continue;
+            }
+            "draw" => {
+                // The opponent offers a draw, which is accepted by
+                // offering one back
+                if adjudicator.accept_draw(board.move_number()) {
+                    println!("offer draw");
+                }
+                continue;
+            }
+            "bk" => {
+                // Book moves for the current position, as lines
+                // starting with a space and ending with an empty line
+                let moves = book_options.book().map_or(Vec::new(), |book| book.moves(&mut board));
+                let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+                let move_format = board.move_format(castle_notation);
+                for (mv, weight) in &moves {
+                    println!(" {} {:.0}%", move_format.format(mv), 100.0 * *weight as f64 / total.max(1) as f64);
+                }
+                if moves.is_empty() {
+                    println!(" no book moves");
+                }
+                println!();
+                continue;
+            }
+            "otim" | "computer" | "name" | "rating" | "accepted" | "rejected" | "hard" | "easy"
+                | "random" | "memory" | "cores" | "hint" | "ics" | "xboard" => {
+                // Nothing to do: there is no pondering, hash table
+                // size or threads
+                continue;
+            }
+            "quit" => {
+                for handle in search.take().into_iter().chain(analysis.take()) {
+                    handle.stop_and_join();
+                }
                 break;
             }
+            "new" | "setboard" | "edit" | "result" | "force" | "white" | "black" => {
+                // The search result is no longer wanted
+                if let Some(handle) = search.take() {
+                    handle.stop_and_join();
+                }
+            }
+            _ => {
+                if finish_search(&mut search, &mut board, &mut time_control, &mut adjudicator, castle_notation, &mut claimed) {
+                    engine_side = BOTH;
+                }
+            }
         }
 
-        match rx.recv() {
-            Ok(input) => {
-                let mut words = input.split_whitespace();
+        // The analysis is restarted when the position changes
+        if let "usermove" | "undo" | "remove" | "setboard" | "new" | "exit" | "white" | "black" = command {
+            if let Some(handle) = analysis.take() {
+                handle.stop_and_join();
+            }
+        }
 
-                match words.next() {
-                    Some("quit") => {
-                        info.quit = true;
-                        break;
-                    }
-                    Some("force") => {
-                        engine_side = BOTH;
-                    }
-                    Some("protover") => {
-                        println!("feature ping=1 setboard=1 colors=0 usermove=1");
-                        // sigint=0 needed on Linux
-                        println!("feature sigint=0");
-                        println!("feature myname=\"{}\"", PROGRAM_NAME);
-                        println!("feature done=1");
-                    }
-                    Some("sd") => {
-                        if let Some(w) = words.next() {
-                            depth = w.parse().unwrap();
+        match command {
+            "force" => {
+                engine_side = BOTH;
+            }
+            "protover" => {
+                println!("feature ping=1 setboard=1 colors=0 usermove=1 analyze=1 playother=1 draw=1 nps=1");
+                println!("feature variants=\"normal,fischerandom,3check,kingofthehill,atomic,crazyhouse,giveaway\"");
+                // sigint=0 needed on Linux
+                println!("feature sigint=0");
+                println!("feature option=\"OwnBook -check 0\"");
+                println!("feature option=\"BookFile -file \"");
+                println!("feature option=\"Best Book Move -check 0\"");
+                println!("feature myname=\"{}\"", PROGRAM_NAME);
+                println!("feature done=1");
+            }
+            "option" => {
+                // option NAME=VALUE, for the options sent in features
+                let option = input["option".len()..].trim();
+                match option.split_once('=') {
+                    Some(("OwnBook", value)) => book_options.own_book = value == "1",
+                    Some(("BookFile", path)) => {
+                        match book_options.load(path) {
+                            Ok(()) => if let Some(book) = book_options.book() {
+                                println!("# loaded book {} with {} entries", path, book.len());
+                            }
+                            Err(e) => println!("# failed to load book {}: {}", path, e),
                         }
                     }
-                    Some("st") => {
-                        if let Some(w) = words.next() {
-                            move_time = Some(w.parse::<u64>().unwrap() * 1000);
-                        }
+                    Some(("Best Book Move", value)) => {
+                        book_options.selection = if value == "1" { BookSelection::Best } else { BookSelection::Weighted };
                     }
-                    Some("time") => {
-                        if let Some(t) = words.next() {
-                            // Centiseconds -> milliseconds
-                            let cs: u64 = t.parse().unwrap();
-                            time = Some(cs * 10); // centiseconds -> milliseconds
-                        }
-                    }
-                    Some("level") => {
+                    _ => error("unknown option", input.trim()),
+                }
+            }
+            "sd" => {
+                match args.first().and_then(|w| w.parse().ok()) {
+                    Some(d) if d > 0 => depth = std::cmp::min(d, MAX_DEPTH),
+                    _ => error("invalid argument", input.trim()),
+                }
+            }
+            "st" => {
+                match args.first().and_then(|w| parse_seconds(w)) {
+                    Some(ms) => move_time = Some(ms),
+                    None => error("invalid argument", input.trim()),
+                }
+            }
+            "time" => {
+                // Centiseconds -> milliseconds
+                match args.first().and_then(|t| t.parse::<u64>().ok()) {
+                    Some(cs) => time = Some(cs * 10),
+                    None => error("invalid argument", input.trim()),
+                }
+            }
+            "nps" => {
+                // Zero turns node based timing off
+                match args.first().and_then(|n| n.parse::<u64>().ok()) {
+                    Some(0) => nps = None,
+                    Some(n) => nps = Some(n),
+                    None => error("invalid argument", input.trim()),
+                }
+            }
+            "level" => {
+                match parse_level(&args) {
+                    Some((moves_per_session, base, increment)) => {
                         move_time = None;
-                        moves_per_session = words.next().unwrap().parse().unwrap();
-                        let time_spec = words.next().unwrap();
-                        inc = Some(words.next().unwrap().parse::<u64>().unwrap() * 1000); // Seconds -> ms
-                        let mut time_args = time_spec.split(':');
-                        time_left = time_args.next().unwrap().parse().unwrap();
-                        time_left *= 60000; // Min -> sec
-                        if let Some(sec) = time_args.next() {
-                            let seconds: u64 = sec.parse().unwrap();
-                            time_left += seconds * 1000;
-                        }
                         time = None;
-                        moves_to_go[0] = 30;
-                        moves_to_go[1] = 30;
+                        inc = Some(increment);
+                        time_control.moves_per_session = moves_per_session;
+                        time_control.moves_to_go = [30, 30];
                         if moves_per_session != 0 {
-                            moves_to_go[0] = moves_per_session;
-                            moves_to_go[1] = moves_per_session;
+                            time_control.moves_to_go = [moves_per_session, moves_per_session];
                         }
-                        println!("level debug: time_left:{} moves_to_go:{} inc:{:?} moves_per_session:{}", time_left, moves_to_go[0], inc, moves_per_session);
-                            
-                    }
-                    Some("ping") => {
-                        println!("pong {}", words.next().unwrap());
-                    }
-                    Some("new") => {
-                        engine_side = BLACK;
-                        board = board.update_from_fen(START_FEN);
-                        depth = MAX_DEPTH;
-                        time = None;
-                        move_time = None;
-                        inc = None;
-                        moves_per_session = 0;
+                        println!("# level: base:{} moves_to_go:{} inc:{:?} moves_per_session:{}", base, time_control.moves_to_go[0], inc, moves_per_session);
                     }
-                    Some("setboard") => {
-                        board = board.update_from_fen(&input[9..].trim_end());
-                    }
-                    Some("go") => {
-                        engine_side = board.side;
+                    None => error("invalid argument", input.trim()),
+                }
+            }
+            "new" => {
+                engine_side = if analyzing { BOTH } else { BLACK };
+                board = board.update_from_fen(START_FEN);
+                castle_notation = CastleNotation::KingMove;
+                board.set_variant(Variant::Standard);
+                adjudicator.reset();
+                depth = MAX_DEPTH;
+                time = None;
+                move_time = None;
+                inc = None;
+                time_control.moves_per_session = 0;
+            }
+            "variant" => {
+                // Sent after new.  The start position follows with
+                // setboard for fischerandom.
+                match args.first() {
+                    Some(&"fischerandom") => {
+                        castle_notation = CastleNotation::OO;
+                        board.set_variant(Variant::Standard);
                     }
-                    Some("usermove") => {
-                        moves_to_go[board.side] -= 1;
-                        if let Some(move_str) = words.next() {
-                            if let Some(mv) = board.parse_move(move_str) {
-                                board.make_move(&mv);
+                    Some(name) => match Variant::from_name(name) {
+                        Some(variant) => {
+                            castle_notation = CastleNotation::KingMove;
+                            board.set_variant(variant);
+                        }
+                        None => error("unsupported variant", input.trim()),
+                    },
+                    None => error("unsupported variant", input.trim()),
+                }
+            }
+            "setboard" => {
+                board = board.update_from_fen(input[9..].trim_end());
+            }
+            "go" => {
+                engine_side = board.side;
+            }
+            "playother" => {
+                engine_side = board.side ^ 1;
+            }
+            "white" | "black" => {
+                // White (black) to move, with the engine playing the
+                // other color
+                let side = if command == "white" { WHITE } else { BLACK };
+                board = set_side(board, side);
+                engine_side = side ^ 1;
+            }
+            "result" => {
+                // The game is over
+                engine_side = BOTH;
+            }
+            "edit" => {
+                editor = Some(Editor::new(&board));
+            }
+            "usermove" => {
+                match args.first() {
+                    Some(move_str) => {
+                        match board.parse_move(move_str) {
+                            Some(mv) if board.make_move(&mv) => {
                                 board.reset_ply();
-                            } else {
-                                println!("invalid user move detected: {}", move_str);
+                                time_control.count_move(board.side^1);
                             }
+                            _ => println!("Illegal move: {}", move_str),
                         }
                     }
-                    Some("remove") => {
-                        // User asked to undo move (happens during
-                        // user turn).  Undo last 2 moves and continue
-                        // playing the same color.
-                        board.undo_move();
-                        board.undo_move();
-                    }
-                    Some("mirror") => {
-                        // For testing board.mirror.  May be removed.
-                        board.print();
-                        println!("Eval: {}", board.evaluate());
-                        board = board.mirror();
-                        board.print();
-                        println!("Eval: {}", board.evaluate());
-                        board = board.mirror();
-                    }
-                    _ => (),
+                    None => error("missing move", input.trim()),
                 }
             }
-            _ => {
-            panic!("stdin channel closed");
+            "remove" => {
+                // User asked to undo move (happens during
+                // user turn).  Undo last 2 moves and continue
+                // playing the same color.
+                board.take_back();
+                board.take_back();
+            }
+            "undo" => {
+                board.take_back();
+            }
+            "analyze" => {
+                analyzing = true;
+                engine_side = BOTH;
+            }
+            "exit" => {
+                analyzing = false;
+            }
+            "eval" => {
+                // Sent as comments so that the GUI ignores it
+                for line in board.evaluate_breakdown().to_string().lines() {
+                    println!("# {}", line);
+                }
+            }
+            "mirror" => {
+                // For testing board.mirror.  May be removed.
+                board.print();
+                println!("Eval: {}", board.evaluate());
+                board = board.mirror();
+                board.print();
+                println!("Eval: {}", board.evaluate());
+                board = board.mirror();
             }
+            _ => error("unknown command", input.trim()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level() {
+        assert_eq!(parse_level(&["40", "5", "0"]), Some((40, 300_000, 0)));
+        assert_eq!(parse_level(&["0", "2:30", "12"]), Some((0, 150_000, 12_000)));
+        assert_eq!(parse_level(&["0", "1", "0.5"]), Some((0, 60_000, 500)));
+        assert_eq!(parse_level(&["40", "x", "0"]), None);
+        assert_eq!(parse_level(&["40", "5"]), None);
+        assert_eq!(parse_seconds("-1"), None);
+    }
+
+    #[test]
+    fn edit_board() {
+        let board = Board::from_fen(START_FEN);
+        let mut editor = Editor::new(&board);
+        assert_eq!(editor.to_fen(), START_FEN);
+
+        for cmd in &["#", "Ke1", "Ra1", "Pe4", "c", "Ke8", "xe8", "Kg8", "Pa7"] {
+            assert!(editor.command(cmd));
         }
+        assert!(! editor.command("Zz9"));
+        assert_eq!(editor.to_fen(), "6k1/p7/8/8/4P3/8/8/R3K3 w Q - 0 1");
+    }
+
+    #[test]
+    fn side_to_move() {
+        let board = set_side(Board::from_fen(START_FEN), BLACK);
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn claim_once() {
+        let mut claimed = None;
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert!(! claim_result(&mut board, &mut claimed));
+        assert_eq!(claimed, None);
+
+        let mv = board.parse_move("a1a8").unwrap();
+        board.make_move(&mv);
+        assert!(claim_result(&mut board, &mut claimed));
+        assert_eq!(claimed, Some(board.to_fen()));
+        // Still over, but already claimed
+        assert!(claim_result(&mut board, &mut claimed));
+        assert_eq!(claimed, Some(board.to_fen()));
     }
 }